@@ -0,0 +1,287 @@
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{Data, DeriveInput, Fields, Member, Type};
+
+/// Implements `Pointer`, `Add`, `Sub`, `BitAnd`, `Not` and conversions for a newtype
+/// over another pointer type. `Copy`, `Clone`, `PartialEq` and `PartialOrd` have to be derived too,
+/// and `Storable` as well for pointers used with `FreeList`, which keeps them in the managed memory.
+#[proc_macro_derive(Pointer)]
+pub fn derive_pointer(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match pointer_impl(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// Implements `Plain` for a `#[repr(C)]` struct, whose fields are all `Plain`.
+/// Fails to compile if the struct has padding, that would be read uninitialized.
+#[proc_macro_derive(Plain)]
+pub fn derive_plain(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match plain_impl(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn has_repr(input: &DeriveInput, repr: &str) -> bool {
+    input.attrs.iter()
+        .filter(|attr| attr.path.is_ident("repr"))
+        .filter_map(|attr| attr.parse_meta().ok())
+        .any(|meta| match meta {
+            syn::Meta::List(list) => list.nested.iter().any(|nested| match *nested {
+                syn::NestedMeta::Meta(ref meta) => meta.path().is_ident(repr),
+                _ => false,
+            }),
+            _ => false,
+        })
+}
+
+fn plain_impl(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        _ => return Err(syn::Error::new_spanned(&input.ident, "Plain can only be derived for structs")),
+    };
+    if !has_repr(input, "C") && !has_repr(input, "transparent") {
+        return Err(syn::Error::new_spanned(&input.ident, "Plain requires #[repr(C)] or #[repr(transparent)]"));
+    }
+    if input.generics.params.iter().next().is_some() {
+        return Err(syn::Error::new_spanned(&input.generics, "Plain can't be derived for generic structs"));
+    }
+    let name = &input.ident;
+    let types: Vec<&Type> = fields.iter().map(|field| &field.ty).collect();
+
+    Ok(quote! {
+        unsafe impl ::mem_bitness::Plain for #name {}
+
+        const _: () = {
+            fn assert_plain<T: ::mem_bitness::Plain>() {}
+            fn assert_fields() { #( assert_plain::<#types>(); )* }
+            assert!(
+                ::std::mem::size_of::<#name>() == 0 #( + ::std::mem::size_of::<#types>() )*,
+                "Plain structs can't have padding"
+            );
+        };
+    })
+}
+
+/// Implements `Storable` for a struct, whose fields are all `Storable`.
+/// Fields are laid out in declaration order, each at the next multiple of its alignment.
+/// `#[storable(packed)]` drops the padding, `#[storable(pack = N)]` caps field alignment at N.
+#[proc_macro_derive(Storable, attributes(storable))]
+pub fn derive_storable(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match storable_impl(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// Maximum field alignment from `#[storable(..)]`, if any.
+fn storable_pack(input: &DeriveInput) -> syn::Result<Option<usize>> {
+    let mut pack = None;
+    for attr in input.attrs.iter().filter(|attr| attr.path.is_ident("storable")) {
+        let list = match attr.parse_meta()? {
+            syn::Meta::List(list) => list,
+            meta => return Err(syn::Error::new_spanned(meta, "expected #[storable(packed)] or #[storable(pack = N)]")),
+        };
+        for nested in list.nested.iter() {
+            pack = Some(match *nested {
+                syn::NestedMeta::Meta(syn::Meta::Path(ref path)) if path.is_ident("packed") => 1,
+                syn::NestedMeta::Meta(syn::Meta::NameValue(ref value)) if value.path.is_ident("pack") => match value.lit {
+                    syn::Lit::Int(ref int) => {
+                        let pack = int.base10_parse::<usize>()?;
+                        if !pack.is_power_of_two() {
+                            return Err(syn::Error::new_spanned(int, "pack must be a power of two"));
+                        }
+                        pack
+                    },
+                    ref lit => return Err(syn::Error::new_spanned(lit, "pack must be an integer")),
+                },
+                ref nested => return Err(syn::Error::new_spanned(nested, "expected `packed` or `pack = N`")),
+            });
+        }
+    }
+    Ok(pack)
+}
+
+fn storable_impl(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        _ => return Err(syn::Error::new_spanned(&input.ident, "Storable can only be derived for structs")),
+    };
+    let pack = match storable_pack(input)? {
+        Some(pack) => quote!(#pack),
+        None => quote!(::std::usize::MAX),
+    };
+    let name = &input.ident;
+    let types: Vec<&Type> = fields.iter().map(|field| &field.ty).collect();
+    let members: Vec<Member> = fields.iter().enumerate().map(|(i, field)| match field.ident {
+        Some(ref ident) => Member::Named(ident.clone()),
+        None => Member::Unnamed(i.into()),
+    }).collect();
+    let indices: Vec<usize> = (0..types.len()).collect();
+    let count = types.len();
+
+    let mut generics = input.generics.clone();
+    for ty in types.iter() {
+        generics.make_where_clause().predicates.push(syn::parse_quote!(#ty: ::mem_bitness::Storable));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let aligns: Vec<TokenStream2> = types.iter().map(|ty| quote! {
+        if <#ty as ::mem_bitness::Storable>::ALIGN < #pack { <#ty as ::mem_bitness::Storable>::ALIGN } else { #pack }
+    }).collect();
+    let end = match types.last() {
+        Some(last) => {
+            let last_index = count - 1;
+            quote!(Self::OFFSETS[#last_index] + <#last as ::mem_bitness::Storable>::SIZE)
+        },
+        None => quote!(0),
+    };
+    let construct = match *fields {
+        Fields::Named(_) => quote! {
+            #name { #( #members: <#types as ::mem_bitness::Storable>::load(&bytes[Self::OFFSETS[#indices]..]), )* }
+        },
+        Fields::Unnamed(_) => quote! {
+            #name ( #( <#types as ::mem_bitness::Storable>::load(&bytes[Self::OFFSETS[#indices]..]), )* )
+        },
+        Fields::Unit => quote!(#name),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::mem_bitness::Storable for #name #ty_generics #where_clause {
+            const SIZE: usize = {
+                let end = #end;
+                usize::div_ceil(end, Self::ALIGN) * Self::ALIGN
+            };
+            const ALIGN: usize = {
+                let mut align = 1;
+                #( { let field = #aligns; if field > align { align = field; } } )*
+                align
+            };
+            const OFFSETS: &'static [usize] = &{
+                let mut offsets = [0; #count];
+                let mut end = 0;
+                #(
+                    let align = #aligns;
+                    offsets[#indices] = usize::div_ceil(end, align) * align;
+                    end = offsets[#indices] + <#types as ::mem_bitness::Storable>::SIZE;
+                )*
+                let _ = end;
+                offsets
+            };
+
+            fn load(bytes: &[u8]) -> Self {
+                let _ = bytes;
+                #construct
+            }
+
+            fn store(&self, bytes: &mut [u8]) {
+                let _ = bytes;
+                #( ::mem_bitness::Storable::store(&self.#members, &mut bytes[Self::OFFSETS[#indices]..]); )*
+            }
+        }
+    })
+}
+
+fn newtype_field(input: &DeriveInput) -> syn::Result<(Member, Type)> {
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        _ => return Err(syn::Error::new_spanned(&input.ident, "Pointer can only be derived for structs")),
+    };
+    let field = match *fields {
+        Fields::Named(ref named) if named.named.len() == 1 => named.named.first(),
+        Fields::Unnamed(ref unnamed) if unnamed.unnamed.len() == 1 => unnamed.unnamed.first(),
+        _ => None,
+    };
+    match field {
+        Some(field) => {
+            let member = match field.ident {
+                Some(ref ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(0.into()),
+            };
+            Ok((member, field.ty.clone()))
+        },
+        None => Err(syn::Error::new_spanned(fields, "Pointer can only be derived for structs with exactly one field")),
+    }
+}
+
+fn pointer_impl(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let (member, inner) = newtype_field(input)?;
+    let name = &input.ident;
+
+    let mut generics = input.generics.clone();
+    generics.make_where_clause().predicates.push(syn::parse_quote!(#inner: ::mem_bitness::Pointer));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let binary = |trait_name: TokenStream2, method: TokenStream2, op: TokenStream2| quote! {
+        impl #impl_generics ::std::ops::#trait_name for #name #ty_generics #where_clause {
+            type Output = Self;
+            fn #method(self, other: Self) -> Self { #name { #member: self.#member #op other.#member } }
+        }
+    };
+    let add = binary(quote!(Add), quote!(add), quote!(+));
+    let sub = binary(quote!(Sub), quote!(sub), quote!(-));
+    let bitand = binary(quote!(BitAnd), quote!(bitand), quote!(&));
+
+    // `From<Name> for Inner` would leave a type parameter uncovered, which coherence rejects.
+    let into_inner = if input.generics.type_params().next().is_none() {
+        quote! {
+            impl ::std::convert::From<#name> for #inner {
+                fn from(value: #name) -> Self { value.#member }
+            }
+        }
+    } else {
+        quote!()
+    };
+
+    Ok(quote! {
+        #add
+        #sub
+        #bitand
+
+        impl #impl_generics ::std::ops::Not for #name #ty_generics #where_clause {
+            type Output = Self;
+            fn not(self) -> Self { #name { #member: !self.#member } }
+        }
+
+        impl #impl_generics ::mem_bitness::Pointer for #name #ty_generics #where_clause {
+            fn from_usize(value: usize) -> Self {
+                #name { #member: <#inner as ::mem_bitness::Pointer>::from_usize(value) }
+            }
+            fn to_usize(self) -> usize { ::mem_bitness::Pointer::to_usize(self.#member) }
+
+            fn max_value() -> Self {
+                #name { #member: <#inner as ::mem_bitness::Pointer>::max_value() }
+            }
+            fn checked_add(self, other: Self) -> ::std::option::Option<Self> {
+                ::mem_bitness::Pointer::checked_add(self.#member, other.#member).map(|value| #name { #member: value })
+            }
+            fn checked_sub(self, other: Self) -> ::std::option::Option<Self> {
+                ::mem_bitness::Pointer::checked_sub(self.#member, other.#member).map(|value| #name { #member: value })
+            }
+
+            type NonZero = <#inner as ::mem_bitness::Pointer>::NonZero;
+            fn to_non_zero(self, null: Self) -> ::std::option::Option<Self::NonZero> {
+                ::mem_bitness::Pointer::to_non_zero(self.#member, null.#member)
+            }
+            fn from_non_zero(value: Self::NonZero, null: Self) -> Self {
+                #name { #member: <#inner as ::mem_bitness::Pointer>::from_non_zero(value, null.#member) }
+            }
+        }
+
+        impl #impl_generics ::std::convert::From<#inner> for #name #ty_generics #where_clause {
+            fn from(value: #inner) -> Self { #name { #member: value } }
+        }
+
+        #into_inner
+    })
+}
@@ -1,37 +1,33 @@
-use std::cmp::PartialOrd;
 use std::alloc::AllocErr;
-use std::ops::Add;
 
 use alloc::{Alloc, Layout};
+use pointer::Pointer;
 
-pub struct BumpAllocator<PTR: Copy> where
-    PTR: PartialOrd,
-    PTR: Add<PTR, Output=PTR>,
-{
-    current: PTR,
+/// Hands out consecutive blocks from `beginning` up to and including `max`.
+pub struct BumpAllocator<PTR: Pointer> {
+    // `None` once the block ending at the max value of `PTR` was handed out
+    current: Option<PTR>,
     max: PTR,
 }
 
-impl<PTR: Copy> BumpAllocator<PTR> where
-    PTR: PartialOrd,
-    PTR: Add<PTR, Output=PTR>,
-{
+impl<PTR: Pointer> BumpAllocator<PTR> {
     pub fn new(beginning: PTR, max: PTR) -> Self {
-        BumpAllocator{current: beginning, max}
+        BumpAllocator{current: Some(beginning), max}
     }
 }
 
-unsafe impl<PTR: Copy> Alloc<PTR> for BumpAllocator<PTR> where
-    PTR: PartialOrd,
-    PTR: Add<PTR, Output=PTR>,
-{
+unsafe impl<PTR: Pointer> Alloc<PTR> for BumpAllocator<PTR> {
     unsafe fn alloc(&mut self, layout: Layout<PTR>) -> Result<PTR, AllocErr> {
-        if self.current + layout.size() > self.max {
-            Err(AllocErr {})
-        } else {
-            let result = self.current;
-            self.current = self.current + layout.size();
-            Ok(result)
+        let current = match self.current {
+            Some(current) if layout.size() != PTR::from_usize(0) => current,
+            _ => return Err(AllocErr {}),
+        };
+        match current.checked_add(layout.size() - PTR::from_usize(1)) {
+            Some(last) if last <= self.max => {
+                self.current = last.checked_add(PTR::from_usize(1));
+                Ok(current)
+            },
+            _ => Err(AllocErr {}),
         }
     }
 
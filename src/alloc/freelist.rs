@@ -1,117 +1,216 @@
-use std::cmp::PartialOrd;
 use std::alloc::AllocErr;
-use std::ops::{Add, Sub, BitAnd, Not};
 
 use alloc::{Alloc, Layout};
-
+use non_null::{AllOnes, NonNullPtr};
+use pointer::Pointer;
+use Storable;
 use typed_ptr::TypedPtr;
 use super::super::Memory;
 
 type NodePtr<PTR> = TypedPtr<Node<PTR>,PTR>;
 type BlockPtr<PTR> = TypedPtr<Block<PTR>,PTR>;
+// Nodes span at least two bytes, so none can start at the all-ones address.
+type NextPtr<PTR> = Option<NonNullPtr<Node<PTR>, PTR, AllOnes>>;
 
-pub struct FreeList<'a, PTR: Copy + From<usize>, MEM: 'a + Memory<PTR>> where
-    PTR: PartialOrd + PartialEq,
-    PTR: Add<PTR, Output=PTR>,
-    PTR: Sub<PTR, Output=PTR>,
-    PTR: BitAnd<PTR, Output=PTR>,
-    PTR: Not<Output=PTR>,
-{
+pub struct FreeList<'a, PTR: Pointer + Storable, MEM: 'a + Memory<PTR>> {
     start: PTR,
-    free: NodePtr<PTR>,
+    free: Head<PTR>,
     max: PTR,
     memory: &'a mut MEM,
 }
 
-#[derive(Clone)]
-struct Node<PTR: Copy + PartialOrd + PartialEq + Clone>{
+/// Where the first free node is kept.
+enum Head<PTR: Pointer + Storable> {
+    Local(Option<NodePtr<PTR>>),
+    /// In the managed memory itself, so the whole allocator state can be persisted or rolled back with it.
+    Anchored(TypedPtr<NextPtr<PTR>, PTR>),
+}
+
+#[derive(Clone, Storable)]
+struct Node<PTR: Pointer + Storable>{
     max: PTR,
-    next: TypedPtr<Node<PTR>, PTR>,
+    next: NextPtr<PTR>,
 }
 
-impl<PTR: Copy + PartialOrd + PartialEq + Clone + From<usize>> Node<PTR> {
-    fn layout() -> Layout<PTR> { unsafe { Layout::new_unchecked::<Node<PTR>>() } }
+impl<PTR: Pointer + Storable> Node<PTR> {
+    fn layout() -> Layout<PTR> { Layout::storable::<Node<PTR>>() }
 }
 
-#[derive(Clone)]
-struct Block<PTR: Copy + Clone> {
+#[derive(Clone, Storable)]
+struct Block<PTR: Pointer + Storable> {
     start: PTR,
     end: PTR,
 }
 
-impl <PTR: Copy + Clone + From<usize>> Block<PTR> {
-    fn layout() -> Layout<PTR> { unsafe { Layout::new_unchecked::<Block<PTR>>() } }
+impl <PTR: Pointer + Storable> Block<PTR> {
+    fn layout() -> Layout<PTR> { Layout::storable::<Block<PTR>>() }
+}
+
+impl<PTR: Pointer + Storable> NodePtr<PTR> {
+    pub unsafe fn size<MEM: Memory<PTR>>(&self, memory: &MEM, heap_start: PTR) -> Option<PTR> {
+        let max = load(memory, heap_start, self).max;
+        (max - self.address()).checked_add(PTR::from_usize(1))
+    }
+}
+
+/// Metadata, that holds addresses of the heap. They are stored as offsets from the start of the heap,
+/// so the heap stays valid, when its bytes are copied to another address.
+trait Relocatable<PTR>: Sized {
+    fn relocate<F: Fn(PTR) -> PTR>(self, f: F) -> Self;
+}
+
+impl<PTR: Pointer + Storable> Relocatable<PTR> for Node<PTR> {
+    fn relocate<F: Fn(PTR) -> PTR>(self, f: F) -> Self {
+        Node { max: f(self.max), next: self.next.relocate(f) }
+    }
+}
+
+impl<PTR: Pointer + Storable> Relocatable<PTR> for Block<PTR> {
+    fn relocate<F: Fn(PTR) -> PTR>(self, f: F) -> Self {
+        Block { start: f(self.start), end: f(self.end) }
+    }
 }
 
-impl<PTR: Copy + Sub<PTR, Output=PTR> + Add<PTR, Output=PTR> + From<usize>> NodePtr<PTR>
-where PTR: PartialOrd + PartialEq {
-    pub unsafe fn size<MEM: Memory<PTR>>(&self, memory: &MEM) -> PTR {
-        let max = self.read(memory).max;
-        max - self.address() + 1.into()
+impl<PTR: Pointer + Storable> Relocatable<PTR> for NextPtr<PTR> {
+    fn relocate<F: Fn(PTR) -> PTR>(self, f: F) -> Self {
+        self.map(|next| NonNullPtr::new(unsafe { TypedPtr::new(f(next.address())) }).expect("free node at the null address"))
     }
 }
 
-impl<'a, PTR: Copy + From<usize>, MEM: Memory<PTR>> FreeList<'a, PTR, MEM> where
-    PTR: PartialOrd + PartialEq,
-    PTR: Add<PTR, Output=PTR>,
-    PTR: Sub<PTR, Output=PTR>,
-    PTR: BitAnd<PTR, Output=PTR>,
-    PTR: Not<Output=PTR>,
+/// Allocator metadata is trusted, so faults accessing it are bugs.
+unsafe fn load<T, PTR, MEM>(memory: &MEM, heap_start: PTR, ptr: &TypedPtr<T, PTR>) -> T
+    where T: Storable + Relocatable<PTR>, PTR: Pointer, MEM: Memory<PTR>
 {
+    match memory.load_metadata::<T>(ptr.address()) {
+        Ok(value) => value.relocate(|offset| heap_start + offset),
+        Err(error) => panic!("allocator metadata at {:#x} is out of reach", error.address().to_usize()),
+    }
+}
+
+unsafe fn store<T, PTR, MEM>(memory: &mut MEM, heap_start: PTR, ptr: &TypedPtr<T, PTR>, value: T)
+    where T: Storable + Relocatable<PTR>, PTR: Pointer, MEM: Memory<PTR>
+{
+    let value = value.relocate(|address| address - heap_start);
+    if let Err(error) = memory.store_metadata(ptr.address(), &value) {
+        panic!("allocator metadata at {:#x} is out of reach", error.address().to_usize())
+    }
+}
+
+impl<'a, PTR: Pointer + Storable, MEM: Memory<PTR>> FreeList<'a, PTR, MEM> {
     pub unsafe fn new(memory: &'a mut MEM, beginning: PTR, max: PTR) -> Self {
         let free_node_layout = Node::layout();
-        if beginning + free_node_layout.size() >= max {
-            panic!("memory region is too small")
+        match beginning.checked_add(free_node_layout.size()) {
+            Some(end) if end <= max => {},
+            _ => panic!("memory region is too small"),
         };
         let free_node = Node {
             max: max,
-            next: FreeList::<'a, PTR, MEM>::invalid_t(beginning, max),
+            next: None,
         };
         let head_ptr = NodePtr::new(beginning);
-        head_ptr.write(memory, free_node);
-        FreeList{start: beginning, max, free: head_ptr, memory}
+        store(memory, beginning, &head_ptr, free_node);
+        FreeList{start: beginning, max, free: Head::Local(Some(head_ptr)), memory}
+    }
+
+    /// Like `new`, but keeps the head of the free list at `anchor` in `memory`, outside of the heap.
+    /// Another `FreeList` can `attach` to the heap later, e.g. after the memory was restored from a snapshot.
+    ///
+    /// # Safety
+    /// Nothing else may use the heap from `beginning` to `max` or the bytes at `anchor`.
+    pub unsafe fn with_anchor(memory: &'a mut MEM, anchor: PTR, beginning: PTR, max: PTR) -> Self {
+        let mut list = FreeList::new(memory, beginning, max);
+        let free = list.free();
+        list.free = list.anchor(anchor);
+        list.set_free(free);
+        list
+    }
+
+    /// Resumes managing a heap created by `with_anchor`. If the heap was copied elsewhere,
+    /// `beginning` and `max` are moved by the same distance, and `anchor` is where its anchor was copied to.
+    ///
+    /// # Safety
+    /// The heap and its anchor have to be left as the last `FreeList` managing them left them,
+    /// and no other `FreeList` may manage them at the same time.
+    pub unsafe fn attach(memory: &'a mut MEM, anchor: PTR, beginning: PTR, max: PTR) -> Self {
+        let mut list = FreeList{start: beginning, max, free: Head::Local(None), memory};
+        list.free = list.anchor(anchor);
+        list
+    }
+
+    fn anchor(&self, anchor: PTR) -> Head<PTR> {
+        match anchor.checked_add(PTR::from_usize(NextPtr::<PTR>::SIZE - 1)) {
+            Some(last) if last < self.start || anchor > self.max => {},
+            _ => panic!("anchor overlaps the heap"),
+        }
+        Head::Anchored(unsafe { TypedPtr::new(anchor) })
+    }
+
+    fn free(&self) -> Option<NodePtr<PTR>> {
+        match self.free {
+            Head::Local(ref free) => free.clone(),
+            Head::Anchored(ref anchor) => unsafe { load(self.memory, self.start, anchor) }.map(|free| free.typed()),
+        }
+    }
+
+    fn set_free(&mut self, free: Option<NodePtr<PTR>>) {
+        match self.free {
+            Head::Local(ref mut head) => *head = free,
+            Head::Anchored(ref anchor) => unsafe { store(self.memory, self.start, anchor, Self::link(free)) },
+        }
     }
 
     fn is_valid(&self, ptr: PTR) -> bool { ptr >= self.start && ptr <= self.max }
     fn is_valid_t<T>(&self, ptr: &TypedPtr<T, PTR>) -> bool { self.is_valid(ptr.address()) }
-    fn invalid_t<T>(_: PTR, max: PTR) -> TypedPtr<T, PTR> {
-        unsafe { TypedPtr::new(max + 1.into()) }
+    fn link(node: Option<NodePtr<PTR>>) -> NextPtr<PTR> {
+        node.map(|node| NonNullPtr::new(node).expect("free node at the null address"))
+    }
+    fn next(&self, node: &NodePtr<PTR>) -> Option<NodePtr<PTR>> {
+        unsafe { load(self.memory, self.start, node) }.next.map(|next| next.typed())
     }
-    fn invalid<T>(&self) -> TypedPtr<T, PTR>{Self::invalid_t::<T>(self.start, self.max)}
     fn minimum_free_block_total_size(&self) -> PTR {
         let node_layout = Node::<PTR>::layout();
-        let empty_size = node_layout.align_offset(1.into()) + node_layout.size();
+        let empty_size = node_layout.align_offset(PTR::from_usize(1)) + node_layout.size();
         let allocated_layout = Block::<PTR>::layout();
-        let allocated_size = allocated_layout.align_offset(1.into()) + allocated_layout.size();
+        let allocated_size = allocated_layout.align_offset(PTR::from_usize(1)) + allocated_layout.size();
         if allocated_size > empty_size { allocated_size } else { empty_size }
     }
 
     fn fits(&self, free_block: NodePtr<PTR>, layout: &Layout<PTR>) -> bool {
+        self.placement(free_block, layout).is_some()
+    }
+
+    /// Where data and block metadata go, if `layout` fits into `free_block`:
+    /// (data start, metadata start, last metadata byte).
+    fn placement(&self, free_block: NodePtr<PTR>, layout: &Layout<PTR>) -> Option<(PTR, PTR, PTR)> {
         if !self.is_valid_t(&free_block){
             panic!("invalid free block");
         }
-        if layout.size() <= 0.into() {
+        if layout.size() <= PTR::from_usize(0) {
             panic!("invalid layout");
         }
 
         let block_start = free_block.address();
-        let block_end_inclusive = unsafe { free_block.read(self.memory).max };
+        let block_end_inclusive = unsafe { load(self.memory, self.start, &free_block).max };
 
-        let free_node_layout = Node::layout();
         let block_metadata_layout = Block::layout();
 
-        let data_start = layout.align_offset(block_start);
-        let metadata_start = block_metadata_layout.align_offset(data_start + layout.size());
-        let metadata_end_exclusive = free_node_layout.align_offset(metadata_start + block_metadata_layout.size());
-        block_end_inclusive + 1.into() >= metadata_end_exclusive
+        let data_start = layout.checked_align_offset(block_start)?;
+        let data_end_exclusive = data_start.checked_add(layout.size())?;
+        let metadata_start = block_metadata_layout.checked_align_offset(data_end_exclusive)?;
+        let metadata_end_inclusive = metadata_start.checked_add(block_metadata_layout.size() - PTR::from_usize(1))?;
+        if metadata_end_inclusive <= block_end_inclusive {
+            Some((data_start, metadata_start, metadata_end_inclusive))
+        } else {
+            None
+        }
     }
 
     fn traverse<F: FnMut(NodePtr<PTR>)>(&self, mut f: F) {
-        let mut current = self.free.clone();
-        while self.is_valid(current.address()) {
-            f(current.clone());
-            let next = unsafe { current.read(self.memory).next };
-            if next == current {
+        let mut current = self.free();
+        while let Some(node) = current {
+            f(node.clone());
+            let next = self.next(&node);
+            if next == Some(node) {
                 panic!("self-loop in traverse")
             }
             current = next;
@@ -119,13 +218,13 @@ impl<'a, PTR: Copy + From<usize>, MEM: Memory<PTR>> FreeList<'a, PTR, MEM> where
     }
 
     fn traverse_while<F: FnMut(NodePtr<PTR>) -> bool>(&self, mut f: F) -> bool {
-        let mut current = self.free.clone();
-        while self.is_valid_t(&current) {
-            if !f(current.clone()){
+        let mut current = self.free();
+        while let Some(node) = current {
+            if !f(node.clone()){
                 return true;
             }
-            let next = unsafe { current.read(self.memory).next };
-            if next == current {
+            let next = self.next(&node);
+            if next == Some(node) {
                 panic!("self-loop in traverse")
             }
             current = next;
@@ -133,160 +232,167 @@ impl<'a, PTR: Copy + From<usize>, MEM: Memory<PTR>> FreeList<'a, PTR, MEM> where
         return false;
     }
 
-    fn remove(&mut self, node: NodePtr<PTR>, prev: NodePtr<PTR>){
+    fn remove(&mut self, node: NodePtr<PTR>, prev: Option<NodePtr<PTR>>){
         if !self.is_valid_t(&node){
             panic!("bad node");
         }
-        if self.is_valid_t(&prev) {
-            let mut prev_node = unsafe { prev.read(self.memory) };
-            if prev_node.next != node {
+        if let Some(prev) = prev {
+            let mut prev_node = unsafe { load(self.memory, self.start, &prev) };
+            if prev_node.next.map(|next| next.typed()) != Some(node.clone()) {
                 panic!("bad prev node");
             }
-            prev_node.next = unsafe { node.read(self.memory) }.next;
-            if prev_node.next == prev {
-                panic!("remove self-loop");
-            }
+            prev_node.next = unsafe { load(self.memory, self.start, &node) }.next;
+            unsafe { self.write_node(&prev, prev_node) };
         } else {
-            if self.free != node {
+            if self.free() != Some(node.clone()) {
                 panic!("prev is missing, but the node is not the first");
             }
-            self.free = unsafe { node.read(self.memory) }.next;
+            let next = self.next(&node);
+            self.set_free(next);
         }
     }
 
-    unsafe fn set_next(&mut self, node_or_invalid: NodePtr<PTR>, next: NodePtr<PTR>){
-        if self.is_valid_t(&node_or_invalid){
-            let mut node_value = node_or_invalid.read(self.memory);
-            node_value.next = next.clone();
-            if node_or_invalid == next {
-                panic!("set_next self-loop");
-            }
-            self.write_node(&node_or_invalid, node_value);
+    unsafe fn set_next(&mut self, node_or_head: Option<NodePtr<PTR>>, next: NodePtr<PTR>){
+        if let Some(node) = node_or_head {
+            let mut node_value = load(self.memory, self.start, &node);
+            node_value.next = Self::link(Some(next));
+            self.write_node(&node, node_value);
         } else {
-            self.free = next;
+            self.set_free(Some(next));
         }
     }
 
     unsafe fn write_node(&mut self, to: &NodePtr<PTR>, node: Node<PTR>) {
-        if node.next == *to {
-            panic!("making a loop!")
-        }
-        if self.is_valid_t(&node.next) & (node.next >= *to) & (node.next.address() <= node.max) {
-            panic!("next node can't be within this node!")
+        if let Some(next) = node.next {
+            if next.typed() == *to {
+                panic!("making a loop!")
+            }
+            if (next.address() >= to.address()) & (next.address() <= node.max) {
+                panic!("next node can't be within this node!")
+            }
         }
-        to.write(self.memory, node);
+        store(self.memory, self.start, to, node);
     }
 }
 
-unsafe impl<'a, PTR: Copy + From<usize>, MEM: Memory<PTR>> Alloc<PTR>
-for FreeList<'a, PTR, MEM> where
-    PTR: PartialOrd + PartialEq,
-    PTR: Add<PTR, Output=PTR>,
-    PTR: Sub<PTR, Output=PTR>,
-    PTR: BitAnd<PTR, Output=PTR>,
-    PTR: Not<Output=PTR>,
-{
+unsafe impl<'a, PTR: Pointer + Storable, MEM: Memory<PTR>> Alloc<PTR> for FreeList<'a, PTR, MEM> {
     unsafe fn alloc(&mut self, layout: Layout<PTR>) -> Result<PTR, AllocErr> {
-        if layout.size() == 0.into() {
+        if layout.size() == PTR::from_usize(0) {
             return Err(AllocErr {})
         }
 
-        let mut prev = self.invalid();
-        let mut target = self.invalid();
+        let mut prev = None;
+        let mut target = None;
         if !self.traverse_while(|free| {
             prev = target.clone();
-            target = free.clone();
+            target = Some(free.clone());
             let continue_search = !self.fits(free, &layout);
             continue_search
         }){
             return Err(AllocErr {});
         }
+        let target = target.expect("traverse_while stopped without a node");
 
-        let free_node_layout = Layout::<PTR>::new_unchecked::<NodePtr<PTR>>();
-        let node = target.read(self.memory);
+        let free_node_layout = Node::layout();
+        let node = load(self.memory, self.start, &target);
 
         let block_start = target.address();
-        let block_end_exclusive = target.read(self.memory).max + 1.into();
-        let block_metadata_layout = Block::layout();
+        let block_end_inclusive = node.max;
+        let (data_start, metadata_start, metadata_end_inclusive) = match self.placement(target.clone(), &layout) {
+            Some(placement) => placement,
+            None => panic!("internal miscalculation"),
+        };
 
-        let data_start = layout.align_offset(block_start);
-        let metadata_start = block_metadata_layout.align_offset(data_start + layout.size());
-        let metadata_end_exclusive = free_node_layout.align_offset(metadata_start + block_metadata_layout.size());
-        if block_end_exclusive < metadata_end_exclusive {
-            panic!("internal miscalculation");
-        }
+        let minimum_free_block_size = self.minimum_free_block_total_size();
+        let new_free_start = metadata_end_inclusive.checked_add(PTR::from_usize(1))
+            .and_then(|end| free_node_layout.checked_align_offset(end))
+            .filter(|&start| match start.checked_add(minimum_free_block_size - PTR::from_usize(1)) {
+                Some(end_inclusive) => end_inclusive <= block_end_inclusive,
+                None => false,
+            });
         let metadata;
 
-        if metadata_end_exclusive + self.minimum_free_block_total_size() <= block_end_exclusive {
-            let new_free_start = NodePtr::new(metadata_end_exclusive);
+        if let Some(new_free_start) = new_free_start {
+            let new_free_start = NodePtr::new(new_free_start);
             self.write_node(&new_free_start, node);
-            metadata = Block { start: block_start, end: metadata_end_exclusive - 1.into() };
+            metadata = Block { start: block_start, end: new_free_start.address() - PTR::from_usize(1) };
             self.set_next(prev, new_free_start);
         } else {
-            metadata = Block { start: block_start, end: block_end_exclusive - 1.into() };
+            metadata = Block { start: block_start, end: block_end_inclusive };
             self.remove(target, prev);
         }
 
-        BlockPtr::new(metadata_start).write(self.memory, metadata);
+        store(self.memory, self.start, &BlockPtr::new(metadata_start), metadata);
+        self.memory.allocated(data_start, layout.size().to_usize());
 
         Ok(data_start)
     }
 
     unsafe fn dealloc(&mut self, ptr: PTR, layout: Layout<PTR>) {
-        if layout.size() <= 0.into() {
+        if layout.size() <= PTR::from_usize(0) {
             panic!("bad dealloc layout")
         }
         if !self.is_valid(ptr){
             panic!("bad ptr")
         }
-        if ptr + layout.size() > self.max + 1.into() {
-            panic!("region past max ptr")
-        }
+        let data_end_exclusive = match ptr.checked_add(layout.size()) {
+            Some(end) if end - PTR::from_usize(1) <= self.max => end,
+            _ => panic!("region past max ptr"),
+        };
 
         let block_metadata_layout = Block::layout();
-        let metadata_start = block_metadata_layout.align_offset(ptr + layout.size());
-        let metadata = BlockPtr::new(metadata_start).read(self.memory);
+        let metadata_start = match block_metadata_layout.checked_align_offset(data_end_exclusive) {
+            Some(start) => start,
+            None => panic!("bad dealloc layout"),
+        };
+        let metadata = load(self.memory, self.start, &BlockPtr::new(metadata_start));
+        self.memory.deallocated(ptr, layout.size().to_usize());
 
-        let mut preceding = self.invalid();
-        let mut prev = self.invalid();
-        let mut pre_succeeding = self.invalid();
-        let mut succeding = self.invalid();
+        let mut preceding = None;
+        let mut prev = None;
+        let mut pre_succeeding = None;
+        let mut succeding = None;
         self.traverse(|free| {
-            let node = free.read(self.memory);
-            if node.max + 1.into() == metadata.start {
-                preceding = free.clone();
+            let node = load(self.memory, self.start, &free);
+            if node.max.checked_add(PTR::from_usize(1)) == Some(metadata.start) {
+                preceding = Some(free.clone());
             }
-            if metadata.end + 1.into() == free.address() {
+            if metadata.end.checked_add(PTR::from_usize(1)) == Some(free.address()) {
                 pre_succeeding = prev.clone();
-                succeding = free.clone();
+                succeding = Some(free.clone());
             }
 
-            prev = free.clone();
+            prev = Some(free.clone());
         });
 
-        if self.is_valid_t(&succeding) && self.is_valid_t(&preceding) {
-            let new_max = succeding.read(self.memory).max;
-            self.remove(succeding, pre_succeeding);
-            let mut new_preceding = preceding.read(self.memory);
-            new_preceding.max = new_max;
-            self.write_node(&preceding, new_preceding);
-        } else if self.is_valid_t(&succeding) {
-            let succeeding_value = succeding.read(self.memory);
-            let new_succeeding_ptr = NodePtr::new(metadata.start);
-            self.write_node(&new_succeeding_ptr, succeeding_value);
-            self.set_next(pre_succeeding, new_succeeding_ptr);
-        } else if self.is_valid_t(&preceding){
-            let mut preceding_value = preceding.read(self.memory);
-            preceding_value.max = metadata.end;
-            self.write_node(&preceding, preceding_value);
-        } else {
-            let region = Node {
-                max: metadata.end,
-                next: self.free.clone(),
-            };
-            let region_ptr = NodePtr::new(metadata.start);
-            self.write_node(&region_ptr, region);
-            self.free = region_ptr;
+        match (preceding, succeding) {
+            (Some(preceding), Some(succeding)) => {
+                let new_max = load(self.memory, self.start, &succeding).max;
+                self.remove(succeding, pre_succeeding);
+                let mut new_preceding = load(self.memory, self.start, &preceding);
+                new_preceding.max = new_max;
+                self.write_node(&preceding, new_preceding);
+            },
+            (None, Some(succeding)) => {
+                let succeeding_value = load(self.memory, self.start, &succeding);
+                let new_succeeding_ptr = NodePtr::new(metadata.start);
+                self.write_node(&new_succeeding_ptr, succeeding_value);
+                self.set_next(pre_succeeding, new_succeeding_ptr);
+            },
+            (Some(preceding), None) => {
+                let mut preceding_value = load(self.memory, self.start, &preceding);
+                preceding_value.max = metadata.end;
+                self.write_node(&preceding, preceding_value);
+            },
+            (None, None) => {
+                let region = Node {
+                    max: metadata.end,
+                    next: Self::link(self.free()),
+                };
+                let region_ptr = NodePtr::new(metadata.start);
+                self.write_node(&region_ptr, region);
+                self.set_free(Some(region_ptr));
+            },
         }
     }
 }
\ No newline at end of file
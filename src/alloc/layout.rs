@@ -1,5 +1,7 @@
 use std::alloc;
-use std::ops::{Add, Sub, BitAnd, Not};
+
+use pointer::Pointer;
+use storable::Storable;
 
 #[derive(Copy)]
 pub struct Layout<PTR: Copy>
@@ -18,15 +20,15 @@ impl<PTR: Copy> Layout<PTR>
     pub fn align(&self) -> PTR { self.align }
 }
 
-impl <PTR: Copy + From<usize>> Layout<PTR>
-where
-    PTR: Add<PTR, Output=PTR>,
-    PTR: Sub<PTR, Output=PTR>,
-    PTR: BitAnd<PTR, Output=PTR>,
-    PTR: Not<Output=PTR>,
+impl <PTR: Pointer> Layout<PTR>
 {
     pub fn align_offset(&self, ptr: PTR) -> PTR {
-        (ptr + self.align - 1.into()) & !(self.align - 1.into())
+        (ptr + self.align - PTR::from_usize(1)) & !(self.align - PTR::from_usize(1))
+    }
+
+    pub fn checked_align_offset(&self, ptr: PTR) -> Option<PTR> {
+        let mask = self.align - PTR::from_usize(1);
+        ptr.checked_add(mask).map(|ptr| ptr & !mask)
     }
 }
 
@@ -37,20 +39,22 @@ impl<PTR: Copy> Clone for Layout<PTR>
     }
 }
 
-impl<PTR: Copy + From<usize>> Layout<PTR>
+impl<PTR: Pointer> Layout<PTR>
 {
     pub unsafe fn new_unchecked<T>() -> Self {
         let layout = alloc::Layout::new::<T>();
-        Layout::from_size_align_unchecked(layout.size().into(), layout.align().into())
+        Layout::from_size_align_unchecked(PTR::from_usize(layout.size()), PTR::from_usize(layout.align()))
+    }
+
+    /// Layout of the `Storable` representation of `T`.
+    pub fn storable<T: Storable>() -> Self {
+        unsafe { Layout::from_size_align_unchecked(PTR::from_usize(T::SIZE), PTR::from_usize(T::ALIGN)) }
     }
 }
 
-impl<PTR: Into<usize> + Copy> From<Layout<PTR>> for alloc::Layout
-where
-    PTR: Add<PTR, Output=PTR>,
-    PTR: Sub<PTR, Output=PTR>,
+impl<PTR: Pointer> From<Layout<PTR>> for alloc::Layout
 {
     fn from(layout: Layout<PTR>) -> alloc::Layout {
-        alloc::Layout::from_size_align(layout.size.into(), layout.align.into()).unwrap()
+        alloc::Layout::from_size_align(layout.size.to_usize(), layout.align.to_usize()).unwrap()
     }
 }
\ No newline at end of file
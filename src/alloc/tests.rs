@@ -1,67 +1,27 @@
-use std::cmp::Ordering;
-use std::ops::{Add, Sub, BitAnd, Not};
+use std::{env, fs, process};
 
 use super::alloc::Alloc;
 use super::bump::BumpAllocator;
 use super::freelist::FreeList;
 use super::layout::Layout;
-use super::super::MemoryRegion;
+use super::super::{Access, Violation, ByteOrder, CompressedMemory, CompressedPtr, EndianMemory, FileRegion, Memory, MemoryMap, MemoryRegion, RustMemory, Pointer, RelPtr, Sanitizer, SegmentedMemory, SegmentedPtr, ShadowState, SparseRegion, Storable, Transaction, TypedPtr, UInt};
 
-#[derive(Copy, Clone, Debug, Eq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Pointer, Storable)]
 struct Ref16(u16);
 
-impl From<Ref16> for usize {
-    fn from(value: Ref16) -> usize { value.0 as usize }
-}
-
-impl PartialEq for Ref16 {
-    fn eq(&self, other: &Ref16) -> bool { self.0 == other.0 }
-}
-
-impl PartialOrd for Ref16 {
-    fn partial_cmp(&self, other: &Ref16) -> Option<Ordering> {
-        self.0.partial_cmp(&other.0)
-    }
-}
-
-impl Add for Ref16 {
-    type Output = Ref16;
-    fn add(self, other: Ref16) -> Self { Ref16(self.0 + other.0) }
-}
-
-impl Sub for Ref16 {
-    type Output = Ref16;
-    fn sub(self, other: Ref16) -> Self { Ref16(self.0 - other.0) }
-}
-
-impl BitAnd for Ref16 {
-    type Output = Ref16;
-    fn bitand(self, other: Ref16) -> Self { Ref16(self.0 & other.0) }
-}
-
-impl Not for Ref16 {
-    type Output = Ref16;
-    fn not(self) -> Self { Ref16(!self.0) }
-}
-
-
-impl From<usize> for Ref16 {
-    fn from(value: usize)-> Self { Ref16(value as u16) }
-}
-
 struct UnevenObject{
     _byte: u8,
     _word: u16,
 }
 
-fn ensure_can_alloc<A: Alloc<PTR>, PTR: Copy>(allocator: &mut A, layout: Layout<PTR>) {
+fn ensure_can_alloc<A: Alloc<PTR>, PTR: Pointer>(allocator: &mut A, layout: Layout<PTR>) {
     unsafe {
         let ptr = allocator.alloc(layout.clone()).unwrap();
         allocator.dealloc(ptr, layout);
     }
 }
 
-unsafe fn fill<A: Alloc<PTR>, PTR: Copy>(allocator: &mut A, allocated: &mut Vec<PTR>, layout: Layout<PTR>) {
+unsafe fn fill<A: Alloc<PTR>, PTR: Pointer>(allocator: &mut A, allocated: &mut Vec<PTR>, layout: Layout<PTR>) {
     loop {
         match allocator.alloc(layout.clone()) {
             Result::Ok(ptr) => allocated.push(ptr),
@@ -74,7 +34,7 @@ unsafe fn fill<A: Alloc<PTR>, PTR: Copy>(allocator: &mut A, allocated: &mut Vec<
     println!("allocated: {}", allocated.len());
 }
 
-unsafe fn allocator_sanity_test<A: Alloc<PTR>, PTR: Copy + From<usize>>(allocator: &mut A) {
+unsafe fn allocator_sanity_test<A: Alloc<PTR>, PTR: Pointer>(allocator: &mut A) {
     let layout = Layout::new_unchecked::<UnevenObject>();
     let mut allocated: Vec<PTR> = Vec::new();
 
@@ -129,6 +89,223 @@ fn freelist_is_sane(){
     }
 }
 
+#[test]
+fn freelist_works_with_primitive_pointers(){
+    let mut backend16 = MemoryRegion::<u16>::new(36);
+    let mut backend32 = MemoryRegion::<u32>::new(36);
+    unsafe {
+        allocator_sanity_test(&mut FreeList::<u16, _>::new(&mut backend16, 0, 35));
+        allocator_sanity_test(&mut FreeList::<u32, _>::new(&mut backend32, 0, 35));
+    }
+}
+
+#[test]
+fn allocators_work_with_odd_pointer_widths(){
+    type U24 = UInt<24>;
+    let mut backend = MemoryRegion::new(U24::new(36));
+    unsafe {
+        allocator_sanity_test(&mut FreeList::new(&mut backend, U24::new(0), U24::new(35)));
+        let mut bump = BumpAllocator::new(U24::new(0xFF_FF00), U24::new(0xFF_FFFF));
+        let layout = Layout::<U24>::new_unchecked::<u16>();
+        assert_eq!(bump.alloc(layout), Ok(U24::new(0xFF_FF00)));
+        assert_eq!(bump.alloc(layout), Ok(U24::new(0xFF_FF02)));
+    }
+}
+
+#[test]
+fn freelist_coalesces_in_larger_heaps(){
+    for &max in [48_u16, 64, 100, 257].iter() {
+        let mut backend = MemoryRegion::<u16>::new(max + 1);
+        unsafe {
+            allocator_sanity_test(&mut FreeList::new(&mut backend, 0, max));
+        }
+    }
+}
+
+#[test]
+fn freelist_covers_whole_address_space(){
+    let mut backend = MemoryRegion::<u16>::full();
+    let layout = unsafe { Layout::<u16>::new_unchecked::<[u32; 64]>() };
+    let mut allocated = Vec::new();
+    unsafe {
+        let mut allocator = FreeList::new(&mut backend, 0, 0xFFFF);
+        fill(&mut allocator, &mut allocated, layout);
+        assert!(allocated.iter().all(|&ptr| ptr.checked_add(layout.size()).is_some()));
+        assert!(allocator.alloc(layout).is_err());
+        for p in allocated.iter() {
+            allocator.dealloc(*p, layout);
+        }
+        allocated.clear();
+        fill(&mut allocator, &mut allocated, layout);
+    }
+    assert_eq!(allocated.len(), 0x10000 / 260);
+
+    let mut backend = MemoryRegion::<u8>::full();
+    let layout = unsafe { Layout::<u8>::new_unchecked::<u16>() };
+    let mut allocated = Vec::new();
+    unsafe {
+        let mut allocator = FreeList::new(&mut backend, 0, 0xFF);
+        fill(&mut allocator, &mut allocated, layout);
+        assert_eq!(allocated.last(), Some(&0xFC));
+        for p in allocated.iter().rev() {
+            allocator.dealloc(*p, layout);
+        }
+        ensure_can_alloc(&mut allocator, layout);
+    }
+    assert_eq!(allocated.len(), 64);
+}
+
+#[test]
+fn bump_allocator_reports_exhaustion_at_max_pointer() {
+    let mut allocator = BumpAllocator::new(0xFFF0_u16, 0xFFFF);
+    unsafe {
+        let layout = Layout::<u16>::new_unchecked::<[u8; 8]>();
+        assert_eq!(allocator.alloc(layout), Ok(0xFFF0));
+        assert_eq!(allocator.alloc(layout), Ok(0xFFF8));
+        assert!(allocator.alloc(layout).is_err());
+        assert!(allocator.alloc(Layout::<u16>::new_unchecked::<u8>()).is_err());
+    }
+
+    let mut allocator = BumpAllocator::new(0_u16, 4);
+    let byte = unsafe { Layout::<u16>::new_unchecked::<u8>() };
+    let allocated: Vec<u16> = (0..6).filter_map(|_| unsafe { allocator.alloc(byte) }.ok()).collect();
+    assert_eq!(allocated, vec![0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn freelist_works_within_a_segment(){
+    let mut backend = SegmentedMemory::new(MemoryRegion::<u32>::new(0x10_0000));
+    unsafe {
+        let start = SegmentedPtr::new(0x2000, 0x0100);
+        let mut allocator = FreeList::new(&mut backend, start, SegmentedPtr::new(0x2000, 0x01FF));
+        let layout = Layout::<SegmentedPtr>::new_unchecked::<u32>();
+        let ptr = allocator.alloc(layout).unwrap();
+        assert_eq!(ptr, start);
+        allocator_sanity_test(&mut allocator);
+        allocator.dealloc(ptr, layout);
+    }
+}
+
+#[test]
+fn freelist_heap_does_not_depend_on_its_address(){
+    let mut backend = MemoryRegion::<u16>::new(0x200);
+    for &start in [0x10_u16, 0x110].iter() {
+        unsafe {
+            let mut allocator = FreeList::new(&mut backend, start, start + 0x7F);
+            let layout = Layout::<u16>::new_unchecked::<[u8; 6]>();
+            let first = allocator.alloc(layout).unwrap();
+            let second = allocator.alloc(layout).unwrap();
+            allocator.alloc(layout).unwrap();
+            allocator.dealloc(first, layout);
+            allocator.dealloc(second, layout);
+        }
+    }
+    for offset in 0..0x80 {
+        assert_eq!(unsafe { backend.read::<u8>(0x10 + offset) }, unsafe { backend.read::<u8>(0x110 + offset) });
+    }
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Item {
+    value: u32,
+    next: RelPtr<Item, i16>,
+}
+
+#[test]
+fn relative_pointers_survive_moving_the_heap(){
+    let mut original = MemoryRegion::<u16>::new(256);
+    let mut moved = MemoryRegion::<u16>::new(1024);
+    unsafe {
+        let layout = Layout::<u16>::new_unchecked::<Item>();
+        let mut items = Vec::new();
+        {
+            let mut allocator = FreeList::new(&mut original, 0, 255);
+            for _ in 0..5 {
+                items.push(TypedPtr::<Item, u16>::new(allocator.alloc(layout).unwrap()));
+            }
+        }
+
+        let mut head = RelPtr::<Item, i16>::null();
+        let mut head_address = 0;
+        for (value, item) in items.iter().enumerate() {
+            let next = match head.resolve(head_address) {
+                Some(target) => RelPtr::new(item.address() + 4, target.address()).unwrap(),
+                None => RelPtr::null(),
+            };
+            item.write(&mut original, Item { value: value as u32, next });
+            head = RelPtr::new(0xF0, item.address()).unwrap();
+            head_address = 0xF0;
+        }
+        original.write(0xF0_u16, head);
+
+        for i in 0..256 {
+            moved.write::<u8>(700 + i, original.read(i));
+        }
+    }
+
+    let mut values = Vec::new();
+    let mut next = unsafe { TypedPtr::<RelPtr<Item, i16>, u16>::new(700 + 0xF0).load(&moved) };
+    while let Some(item) = next {
+        values.push(unsafe { item.read(&moved) }.value);
+        next = unsafe { TypedPtr::<RelPtr<Item, i16>, u16>::new(item.address() + 4).load(&moved) };
+    }
+    assert_eq!(values, vec![4, 3, 2, 1, 0]);
+}
+
+#[test]
+fn freelist_works_with_compressed_pointers(){
+    let mut heap = vec![0_u64; 512];
+    let mut backend = CompressedMemory::<_, 3>::new(RustMemory(), heap.as_mut_ptr() as usize);
+    assert_eq!(backend.compress(heap.as_ptr() as usize + 24), Some(CompressedPtr::new(3)));
+    assert_eq!(backend.compress(heap.as_ptr() as usize + 25), None);
+    unsafe {
+        let mut allocator = FreeList::new(&mut backend, CompressedPtr::new(0), CompressedPtr::new(511));
+        allocator_sanity_test(&mut allocator);
+
+        let layout = Layout::<CompressedPtr<3>>::new_unchecked::<[u32; 3]>();
+        assert_eq!(layout.size(), CompressedPtr::new(2));
+        let ptr = TypedPtr::<[u32; 3], _>::new(allocator.alloc(layout).unwrap());
+        ptr.write(&mut backend, [1, 2, 3]);
+        assert_eq!(heap[(ptr.address().granule() + 1) as usize].to_ne_bytes()[..4], 3_u32.to_ne_bytes());
+    }
+}
+
+#[test]
+fn freelist_heap_persists_in_a_file(){
+    let path = env::temp_dir().join(format!("mem_bitness_freelist_{}.bin", process::id()));
+    let ptr;
+    {
+        let mut backend = FileRegion::<u16>::create(&path, 1024).unwrap();
+        unsafe {
+            let layout = Layout::storable::<[u32; 4]>();
+            ptr = TypedPtr::<[u32; 4], u16>::new(FreeList::new(&mut backend, 0, 1023).alloc(layout).unwrap());
+            ptr.set(&mut backend, &[1, 2, 3, 4]).unwrap();
+        }
+        backend.flush().unwrap();
+    }
+
+    let reopened = FileRegion::<u16>::open(&path, 1024).unwrap();
+    assert_eq!(unsafe { ptr.get(&reopened) }, Ok([1, 2, 3, 4]));
+    assert_eq!(fs::metadata(&path).unwrap().len(), 1024);
+    drop(reopened);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn freelist_spans_sparse_32_bit_address_space(){
+    let mut backend = SparseRegion::<u32>::sparse_full();
+    unsafe {
+        let mut allocator = FreeList::new(&mut backend, 0x1000, 0xFFFF_FFFF);
+        let big = allocator.alloc(Layout::from_size_align_unchecked(0x8000_0000, 16)).unwrap();
+        let small = allocator.alloc(Layout::storable::<u64>()).unwrap();
+        assert!(small > big + 0x7FFF_FFFF);
+        allocator.dealloc(big, Layout::from_size_align_unchecked(0x8000_0000, 16));
+        ensure_can_alloc(&mut allocator, Layout::from_size_align_unchecked(0x8000_0000, 16));
+    }
+    assert!(backend.resident_pages() <= 4);
+}
+
 #[test]
 fn gives_two_different_pointers() {
     let mut allocator = BumpAllocator::new(Ref16(0), Ref16(4));
@@ -151,3 +328,214 @@ fn panics_on_exhaustion() {
         assert!(ptr3.is_err());
     }
 }
+
+#[test]
+fn anchored_freelist_rolls_back_with_snapshots(){
+    let mut backend = SparseRegion::<u16>::sparse_full();
+    let layout = Layout::storable::<u64>();
+    unsafe {
+        let first = FreeList::with_anchor(&mut backend, 0, 0x10, 0xFFFF).alloc(layout).unwrap();
+        TypedPtr::<u64, u16>::new(first).set(&mut backend, &1).unwrap();
+        let saved = backend.snapshot();
+
+        let second = {
+            let mut allocator = FreeList::attach(&mut backend, 0, 0x10, 0xFFFF);
+            let second = allocator.alloc(layout).unwrap();
+            allocator.dealloc(first, layout);
+            allocator.alloc(Layout::storable::<[u64; 4]>()).unwrap();
+            second
+        };
+        TypedPtr::<u64, u16>::new(first).set(&mut backend, &2).unwrap();
+
+        backend.restore(&saved);
+        assert_eq!(TypedPtr::<u64, u16>::new(first).get(&backend), Ok(1));
+        let mut allocator = FreeList::attach(&mut backend, 0, 0x10, 0xFFFF);
+        assert_ne!(second, first);
+        assert_eq!(allocator.alloc(layout), Ok(second));
+        allocator.dealloc(first, layout);
+        ensure_can_alloc(&mut allocator, Layout::storable::<[u64; 4]>());
+    }
+}
+
+#[test]
+#[should_panic]
+fn freelist_anchor_must_be_outside_of_the_heap(){
+    let mut backend = MemoryRegion::<u16>::new(64);
+    unsafe { FreeList::with_anchor(&mut backend, 0x20, 0, 63); }
+}
+
+#[test]
+fn freelist_heap_works_after_being_copied_elsewhere(){
+    let mut backend = MemoryRegion::<u16>::new(0x200);
+    let layout = Layout::storable::<u32>();
+    unsafe {
+        let (kept, freed) = {
+            let mut allocator = FreeList::with_anchor(&mut backend, 0, 0x10, 0x8F);
+            let kept = allocator.alloc(layout).unwrap();
+            let freed = allocator.alloc(layout).unwrap();
+            allocator.alloc(layout).unwrap();
+            allocator.dealloc(freed, layout);
+            (kept, freed)
+        };
+        TypedPtr::<u32, u16>::new(kept).set(&mut backend, &0xC0FFEE).unwrap();
+        backend.copy_within(0, 0x100, 0x90).unwrap();
+        backend.fill(0, 0x90, 0xFF).unwrap();
+
+        assert_eq!(TypedPtr::<u32, u16>::new(kept + 0x100).get(&backend), Ok(0xC0FFEE));
+        let mut allocator = FreeList::attach(&mut backend, 0x100, 0x110, 0x18F);
+        assert_eq!(allocator.alloc(layout), Ok(freed + 0x100));
+        allocator.dealloc(kept + 0x100, layout);
+        ensure_can_alloc(&mut allocator, Layout::storable::<[u32; 4]>());
+    }
+}
+
+#[test]
+fn aborted_transactions_undo_allocations(){
+    let mut backend = MemoryRegion::<u16>::new(0x400);
+    let layout = Layout::storable::<u32>();
+    unsafe {
+        let kept = FreeList::with_anchor(&mut backend, 0, 0x10, 0x3FF).alloc(layout).unwrap();
+        TypedPtr::<u32, u16>::new(kept).set(&mut backend, &5).unwrap();
+
+        let mut transaction = Transaction::new(&mut backend);
+        let speculative = {
+            let mut allocator = FreeList::attach(&mut transaction, 0, 0x10, 0x3FF);
+            allocator.dealloc(kept, layout);
+            allocator.alloc(Layout::storable::<[u32; 8]>()).unwrap()
+        };
+        TypedPtr::<[u32; 8], u16>::new(speculative).set(&mut transaction, &[7; 8]).unwrap();
+        assert_eq!(speculative, kept);
+        transaction.abort();
+        assert_eq!(TypedPtr::<u32, u16>::new(kept).get(&backend), Ok(5));
+
+        let mut allocator = FreeList::attach(&mut backend, 0, 0x10, 0x3FF);
+        let next = allocator.alloc(layout).unwrap();
+        assert!(next > kept);
+        allocator.dealloc(kept, layout);
+        allocator.dealloc(next, layout);
+        allocator_sanity_test(&mut allocator);
+    }
+}
+
+#[test]
+fn sanitizer_reports_uninitialized_and_freed_reads(){
+    let mut backend = Sanitizer::new(MemoryRegion::<u16>::new(0x100));
+    let layout = Layout::storable::<u32>();
+    unsafe {
+        let first = FreeList::with_anchor(&mut backend, 0, 0x10, 0xFF).alloc(layout).unwrap();
+        let second = FreeList::attach(&mut backend, 0, 0x10, 0xFF).alloc(layout).unwrap();
+        assert_eq!(backend.reports().len(), 0);
+
+        backend.write::<u16>(first, 1);
+        backend.read::<u32>(first);
+        backend.write::<u32>(second, 2);
+        assert_eq!(backend.read::<u32>(second), 2);
+        FreeList::attach(&mut backend, 0, 0x10, 0xFF).dealloc(first, layout);
+        backend.read::<u32>(first);
+        backend.read::<u8>(0xF0);
+
+        let reports = backend.take_reports();
+        assert_eq!(reports.iter().map(|report| report.violation()).collect::<Vec<_>>(),
+                   vec![Violation::Uninitialized, Violation::UseAfterFree, Violation::Unallocated]);
+        assert_eq!((reports[0].access(), reports[0].address(), reports[0].size()), (Access::Read, first + 2, 4));
+        let owner = reports[0].allocation().unwrap();
+        assert_eq!((owner.start(), owner.size(), owner.is_freed()), (first, 4, false));
+        assert_eq!(reports[1].to_string(), format!("use after free in read of 4 bytes at {:#x}, 0 bytes into freed allocation of 4 bytes at {:#x}", first, first));
+        assert_eq!(reports[2].allocation(), None);
+
+        let third = FreeList::attach(&mut backend, 0, 0x10, 0xFF).alloc(layout).unwrap();
+        assert_eq!(third, first);
+        backend.write::<u32>(third, 3);
+        assert_eq!(backend.read::<u32>(third), 3);
+        assert_eq!(backend.allocation(third + 3).map(|allocation| allocation.is_freed()), Some(false));
+        assert_eq!(backend.reports().len(), 0);
+    }
+}
+
+#[test]
+fn sanitizer_sees_allocations_through_other_wrappers(){
+    let mut endian = EndianMemory::new(Sanitizer::new(MemoryRegion::<u16>::new(0x100)), ByteOrder::Big);
+    let mut segmented = SegmentedMemory::new(Sanitizer::new(MemoryRegion::<u32>::new(0x10_0000)));
+    let mut heap = vec![0_u64; 64];
+    let mut compressed = CompressedMemory::<_, 3>::new(Sanitizer::new(RustMemory()), heap.as_mut_ptr() as usize);
+    unsafe {
+        let mut allocator = FreeList::new(&mut endian, 0x10, 0xFF);
+        allocator_sanity_test(&mut allocator);
+        let freed = allocator.alloc(Layout::storable::<u32>()).unwrap();
+        allocator.dealloc(freed, Layout::storable::<u32>());
+        endian.read::<u32>(freed);
+        let reports = endian.inner_mut().take_reports();
+        assert_eq!(reports.iter().map(|report| report.violation()).collect::<Vec<_>>(), vec![Violation::UseAfterFree]);
+
+        // the heap wraps around at 1 MiB
+        let mut allocator = FreeList::new(&mut segmented, SegmentedPtr::new(0xFFF0, 0x00F0), SegmentedPtr::new(0xFFF0, 0x01EF));
+        allocator_sanity_test(&mut allocator);
+        let freed = allocator.alloc(Layout::storable::<u32>()).unwrap();
+        allocator.dealloc(freed, Layout::storable::<u32>());
+        segmented.read::<u32>(freed);
+        let reports = segmented.inner_mut().take_reports();
+        assert_eq!(reports.iter().map(|report| report.violation()).collect::<Vec<_>>(), vec![Violation::UseAfterFree]);
+
+        let mut allocator = FreeList::new(&mut compressed, CompressedPtr::new(0), CompressedPtr::new(63));
+        allocator_sanity_test(&mut allocator);
+        let freed = allocator.alloc(Layout::storable::<u32>()).unwrap();
+        allocator.dealloc(freed, Layout::storable::<u32>());
+        compressed.read::<u32>(freed);
+        let reports = compressed.inner_mut().take_reports();
+        assert_eq!(reports.iter().map(|report| report.violation()).collect::<Vec<_>>(), vec![Violation::UseAfterFree]);
+    }
+}
+
+#[test]
+fn sanitizer_sees_allocations_through_a_memory_map(){
+    let mut ram = Sanitizer::new(MemoryRegion::<u16>::new(0x100));
+    {
+        let mut bus = MemoryMap::new();
+        bus.map(0x1000, 0x10FF, &mut ram);
+        bus.mirror(0x1100, 0x11FF, 0x1000, 0x100);
+        unsafe {
+            let mut allocator = FreeList::new(&mut bus, 0x1010, 0x10FF);
+            allocator_sanity_test(&mut allocator);
+            let kept = allocator.alloc(Layout::storable::<u32>()).unwrap();
+            let freed = allocator.alloc(Layout::storable::<u32>()).unwrap();
+            allocator.dealloc(freed, Layout::storable::<u32>());
+            bus.write::<u32>(kept + 0x100, 1);
+            assert_eq!(bus.read::<u32>(kept), 1);
+            bus.read::<u32>(freed);
+        }
+    }
+    let reports = ram.take_reports();
+    assert_eq!(reports.len(), 1);
+    assert_eq!((reports[0].violation(), reports[0].access()), (Violation::UseAfterFree, Access::Read));
+}
+
+#[test]
+fn aborted_transactions_undo_allocator_hooks(){
+    let mut sanitizer = Sanitizer::new(MemoryRegion::<u16>::new(0x100));
+    let layout = Layout::storable::<u32>();
+    unsafe {
+        let kept = FreeList::with_anchor(&mut sanitizer, 0, 0x10, 0xFF).alloc(layout).unwrap();
+        sanitizer.write::<u32>(kept, 5);
+
+        let mut transaction = Transaction::new(&mut sanitizer);
+        let fresh = {
+            let mut allocator = FreeList::attach(&mut transaction, 0, 0x10, 0xFF);
+            let fresh = allocator.alloc(layout).unwrap();
+            allocator.dealloc(kept, layout);
+            fresh
+        };
+        transaction.write::<u32>(fresh, 7);
+        transaction.abort();
+
+        assert_eq!(sanitizer.shadow().state(fresh as usize), ShadowState::Unallocated);
+        assert!(sanitizer.allocation(kept).is_some_and(|allocation| !allocation.is_freed()));
+        sanitizer.write::<u32>(kept, 6);
+        assert_eq!(sanitizer.reports().len(), 0);
+        sanitizer.read::<u32>(fresh);
+        let reports = sanitizer.take_reports();
+        assert_eq!(reports.iter().map(|report| report.violation()).collect::<Vec<_>>(), vec![Violation::UseAfterFree]);
+
+        let mut allocator = FreeList::attach(&mut sanitizer, 0, 0x10, 0xFF);
+        assert_eq!(allocator.alloc(layout), Ok(fresh));
+    }
+}
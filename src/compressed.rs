@@ -0,0 +1,158 @@
+use std::cmp::Ordering;
+use std::num::NonZeroU32;
+use std::ops::{Add, Sub, BitAnd, Not};
+
+use memory::{Access, Memory, MemoryError};
+use pointer::Pointer;
+
+/// 32-bit handle, that addresses 2^SHIFT byte granules, JVM-style.
+///
+/// All arithmetic is done in granules: `from_usize` rounds a byte count up to whole granules,
+/// and `to_usize` gives the byte offset of the handle.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
+pub struct CompressedPtr<const SHIFT: u32>(u32);
+
+impl<const SHIFT: u32> CompressedPtr<SHIFT> {
+    pub fn new(granule: u32) -> Self { CompressedPtr(granule) }
+    pub fn granule(&self) -> u32 { self.0 }
+}
+
+impl<const SHIFT: u32> Add for CompressedPtr<SHIFT> {
+    type Output = Self;
+    fn add(self, other: Self) -> Self { CompressedPtr(self.0 + other.0) }
+}
+
+impl<const SHIFT: u32> Sub for CompressedPtr<SHIFT> {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self { CompressedPtr(self.0 - other.0) }
+}
+
+impl<const SHIFT: u32> BitAnd for CompressedPtr<SHIFT> {
+    type Output = Self;
+    fn bitand(self, other: Self) -> Self { CompressedPtr(self.0 & other.0) }
+}
+
+impl<const SHIFT: u32> Not for CompressedPtr<SHIFT> {
+    type Output = Self;
+    fn not(self) -> Self { CompressedPtr(!self.0) }
+}
+
+impl<const SHIFT: u32> Pointer for CompressedPtr<SHIFT> {
+    fn from_usize(value: usize) -> Self {
+        CompressedPtr(((value >> SHIFT) + (value & ((1 << SHIFT) - 1) != 0) as usize) as u32)
+    }
+    fn to_usize(self) -> usize { (self.0 as usize) << SHIFT }
+
+    fn max_value() -> Self { CompressedPtr(u32::MAX) }
+    fn checked_add(self, other: Self) -> Option<Self> { self.0.checked_add(other.0).map(CompressedPtr) }
+    fn checked_sub(self, other: Self) -> Option<Self> { self.0.checked_sub(other.0).map(CompressedPtr) }
+
+    type NonZero = NonZeroU32;
+    fn to_non_zero(self, null: Self) -> Option<NonZeroU32> { NonZeroU32::new(self.0 ^ null.0) }
+    fn from_non_zero(value: NonZeroU32, null: Self) -> Self { CompressedPtr(value.get() ^ null.0) }
+}
+
+/// Resolves `CompressedPtr`s as `base + (granule << SHIFT)` in the underlying memory,
+/// so with SHIFT = 3 a 32 GiB heap is addressable with 4-byte pointers.
+pub struct CompressedMemory<MEM: Memory<usize>, const SHIFT: u32> {
+    memory: MEM,
+    base: usize,
+}
+
+impl<MEM: Memory<usize>, const SHIFT: u32> CompressedMemory<MEM, SHIFT> {
+    pub fn new(memory: MEM, base: usize) -> Self { CompressedMemory { memory, base } }
+
+    pub fn base(&self) -> usize { self.base }
+    pub fn inner(&self) -> &MEM { &self.memory }
+    pub fn inner_mut(&mut self) -> &mut MEM { &mut self.memory }
+    pub fn into_inner(self) -> MEM { self.memory }
+
+    pub fn decompress(&self, ptr: CompressedPtr<SHIFT>) -> usize { self.base + ptr.to_usize() }
+
+    /// `None` if `address` is below base, not granule-aligned, or too far to fit into 32 bits.
+    pub fn compress(&self, address: usize) -> Option<CompressedPtr<SHIFT>> {
+        let offset = address.checked_sub(self.base)?;
+        let granule = offset >> SHIFT;
+        if offset & ((1 << SHIFT) - 1) != 0 || granule > u32::MAX as usize {
+            return None;
+        }
+        Some(CompressedPtr(granule as u32))
+    }
+}
+
+impl<MEM: Memory<usize>, const SHIFT: u32> Memory<CompressedPtr<SHIFT>> for CompressedMemory<MEM, SHIFT> {
+    unsafe fn read<T>(&self, ptr: CompressedPtr<SHIFT>) -> T { self.memory.read(self.decompress(ptr)) }
+
+    unsafe fn write<T>(&mut self, ptr: CompressedPtr<SHIFT>, value: T) {
+        let address = self.decompress(ptr);
+        self.memory.write(address, value)
+    }
+
+    unsafe fn try_read<T>(&self, ptr: CompressedPtr<SHIFT>) -> Result<T, MemoryError<CompressedPtr<SHIFT>>> {
+        self.memory.try_read(self.decompress(ptr)).map_err(|error| error.at(ptr))
+    }
+
+    unsafe fn try_write<T>(&mut self, ptr: CompressedPtr<SHIFT>, value: T) -> Result<(), MemoryError<CompressedPtr<SHIFT>>> {
+        let address = self.decompress(ptr);
+        self.memory.try_write(address, value).map_err(|error| error.at(ptr))
+    }
+
+    unsafe fn try_fetch<T>(&self, ptr: CompressedPtr<SHIFT>) -> Result<T, MemoryError<CompressedPtr<SHIFT>>> {
+        self.memory.try_fetch(self.decompress(ptr)).map_err(|error| error.at(ptr))
+    }
+
+    unsafe fn read_bytes(&self, ptr: CompressedPtr<SHIFT>, buffer: &mut [u8]) -> Result<(), MemoryError<CompressedPtr<SHIFT>>> {
+        self.memory.read_bytes(self.decompress(ptr), buffer).map_err(|error| error.at(ptr))
+    }
+
+    unsafe fn fetch_bytes(&self, ptr: CompressedPtr<SHIFT>, buffer: &mut [u8]) -> Result<(), MemoryError<CompressedPtr<SHIFT>>> {
+        self.memory.fetch_bytes(self.decompress(ptr), buffer).map_err(|error| error.at(ptr))
+    }
+
+    unsafe fn write_bytes(&mut self, ptr: CompressedPtr<SHIFT>, bytes: &[u8]) -> Result<(), MemoryError<CompressedPtr<SHIFT>>> {
+        let address = self.decompress(ptr);
+        self.memory.write_bytes(address, bytes).map_err(|error| error.at(ptr))
+    }
+
+    unsafe fn fill(&mut self, ptr: CompressedPtr<SHIFT>, len: usize, value: u8) -> Result<(), MemoryError<CompressedPtr<SHIFT>>> {
+        let address = self.decompress(ptr);
+        self.memory.fill(address, len, value).map_err(|error| error.at(ptr))
+    }
+
+    unsafe fn copy_within(&mut self, from: CompressedPtr<SHIFT>, to: CompressedPtr<SHIFT>, len: usize)
+        -> Result<(), MemoryError<CompressedPtr<SHIFT>>>
+    {
+        let (source, destination) = (self.decompress(from), self.decompress(to));
+        self.memory.copy_within(source, destination, len)
+            .map_err(|error| error.at(if error.access() == Access::Read { from } else { to }))
+    }
+
+    /// Both operands are read separately, so a fault is reported at the operand it belongs to.
+    unsafe fn compare(&self, a: CompressedPtr<SHIFT>, b: CompressedPtr<SHIFT>, len: usize)
+        -> Result<Ordering, MemoryError<CompressedPtr<SHIFT>>>
+    {
+        let (mut left, mut right) = (vec![0; len], vec![0; len]);
+        self.read_bytes(a, &mut left)?;
+        self.read_bytes(b, &mut right)?;
+        Ok(left.cmp(&right))
+    }
+
+    fn allocated(&mut self, ptr: CompressedPtr<SHIFT>, size: usize) {
+        let address = self.decompress(ptr);
+        self.memory.allocated(address, size)
+    }
+
+    fn deallocated(&mut self, ptr: CompressedPtr<SHIFT>, size: usize) {
+        let address = self.decompress(ptr);
+        self.memory.deallocated(address, size)
+    }
+
+    unsafe fn read_metadata(&self, ptr: CompressedPtr<SHIFT>, buffer: &mut [u8]) -> Result<(), MemoryError<CompressedPtr<SHIFT>>> {
+        self.memory.read_metadata(self.decompress(ptr), buffer).map_err(|error| error.at(ptr))
+    }
+
+    unsafe fn write_metadata(&mut self, ptr: CompressedPtr<SHIFT>, bytes: &[u8]) -> Result<(), MemoryError<CompressedPtr<SHIFT>>> {
+        let address = self.decompress(ptr);
+        self.memory.write_metadata(address, bytes).map_err(|error| error.at(ptr))
+    }
+}
@@ -0,0 +1,107 @@
+use std::cell::RefCell;
+use std::mem;
+use std::slice;
+
+use memory::{Memory, MemoryError};
+use plain::CheckedMemory;
+use pointer::Pointer;
+
+/// Something, whose registers run code on access, like a timer or a UART.
+///
+/// Handlers get the offset from the start of the mount and the access size: 1, 2, 4 or 8 bytes.
+/// Other accesses are split into single bytes. Values are numbers, laid out in host byte order.
+///
+/// Devices are mounted into a `MemoryMap`; to put one over a plain `MemoryRegion`,
+/// map the region into a `MemoryMap` first and mount the device on top of it.
+pub trait Device<PTR> {
+    fn read(&mut self, offset: PTR, size: usize) -> u64;
+    fn write(&mut self, offset: PTR, size: usize, value: u64);
+}
+
+impl<PTR, D: Device<PTR> + ?Sized> Device<PTR> for &mut D {
+    fn read(&mut self, offset: PTR, size: usize) -> u64 { (**self).read(offset, size) }
+    fn write(&mut self, offset: PTR, size: usize, value: u64) { (**self).write(offset, size, value) }
+}
+
+/// Adapts a `Device` to `Memory`. Reads go through `&self`, but devices may change state on reads.
+pub struct Mounted<D> {
+    device: RefCell<D>,
+}
+
+impl<D> Mounted<D> {
+    pub fn new(device: D) -> Self { Mounted { device: RefCell::new(device) } }
+}
+
+fn to_bytes(value: u64, bytes: &mut [u8]) {
+    match bytes.len() {
+        1 => bytes[0] = value as u8,
+        2 => bytes.copy_from_slice(&(value as u16).to_ne_bytes()),
+        4 => bytes.copy_from_slice(&(value as u32).to_ne_bytes()),
+        _ => bytes.copy_from_slice(&value.to_ne_bytes()),
+    }
+}
+
+fn from_bytes(bytes: &[u8]) -> u64 {
+    let mut buffer = [0; 8];
+    buffer[..bytes.len()].copy_from_slice(bytes);
+    match bytes.len() {
+        1 => bytes[0] as u64,
+        2 => u16::from_ne_bytes([buffer[0], buffer[1]]) as u64,
+        4 => u32::from_ne_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as u64,
+        _ => u64::from_ne_bytes(buffer),
+    }
+}
+
+fn sized(len: usize) -> bool {
+    len == 1 || len == 2 || len == 4 || len == 8
+}
+
+fn read_into<PTR: Pointer, D: Device<PTR>>(device: &mut D, offset: PTR, buffer: &mut [u8]) {
+    if sized(buffer.len()) {
+        let value = device.read(offset, buffer.len());
+        return to_bytes(value, buffer);
+    }
+    for (i, byte) in buffer.iter_mut().enumerate() {
+        *byte = device.read(offset + PTR::from_usize(i), 1) as u8;
+    }
+}
+
+fn write_from<PTR: Pointer, D: Device<PTR>>(device: &mut D, offset: PTR, bytes: &[u8]) {
+    if sized(bytes.len()) {
+        return device.write(offset, bytes.len(), from_bytes(bytes));
+    }
+    for (i, &byte) in bytes.iter().enumerate() {
+        device.write(offset + PTR::from_usize(i), 1, byte as u64);
+    }
+}
+
+impl<PTR: Pointer, D: Device<PTR>> Memory<PTR> for Mounted<D> {
+    unsafe fn read<T>(&self, ptr: PTR) -> T {
+        let mut value = mem::MaybeUninit::<T>::zeroed();
+        read_into(&mut *self.device.borrow_mut(), ptr, slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, mem::size_of::<T>()));
+        value.assume_init()
+    }
+
+    unsafe fn write<T>(&mut self, ptr: PTR, value: T) {
+        let value = mem::ManuallyDrop::new(value);
+        write_from(self.device.get_mut(), ptr, slice::from_raw_parts(&*value as *const T as *const u8, mem::size_of::<T>()))
+    }
+
+    unsafe fn read_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        read_into(&mut *self.device.borrow_mut(), ptr, buffer);
+        Ok(())
+    }
+
+    // devices don't tell code from data
+    unsafe fn fetch_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.read_bytes(ptr, buffer)
+    }
+
+    unsafe fn write_bytes(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> {
+        write_from(self.device.get_mut(), ptr, bytes);
+        Ok(())
+    }
+}
+
+// devices accept any offset, so reads of every value are defined
+unsafe impl<PTR: Pointer, D: Device<PTR>> CheckedMemory<PTR> for Mounted<D> {}
@@ -0,0 +1,230 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use memory::{Memory, MemoryError};
+use plain::{CheckedMemory, Plain};
+use pointer::Pointer;
+use typed_ptr::TypedPtr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    pub fn native() -> ByteOrder {
+        if cfg!(target_endian = "big") { ByteOrder::Big } else { ByteOrder::Little }
+    }
+}
+
+/// Scalar, that can be stored in either byte order.
+pub trait Endian: Plain {
+    fn swap_bytes(self) -> Self;
+}
+
+macro_rules! impl_endian {
+    ($($t:ty),*) => {
+        $( impl Endian for $t { fn swap_bytes(self) -> Self { <$t>::swap_bytes(self) } } )*
+    };
+}
+
+impl_endian!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+impl Endian for f32 {
+    fn swap_bytes(self) -> Self { f32::from_bits(self.to_bits().swap_bytes()) }
+}
+
+impl Endian for f64 {
+    fn swap_bytes(self) -> Self { f64::from_bits(self.to_bits().swap_bytes()) }
+}
+
+macro_rules! endian_wrapper {
+    ($name:ident, $order:expr, $doc:expr) => {
+        #[doc = $doc]
+        #[derive(Copy, Clone, PartialEq, Eq, Hash)]
+        #[repr(transparent)]
+        pub struct $name<T: Endian> {
+            stored: T,
+        }
+
+        impl<T: Endian> $name<T> {
+            pub fn new(value: T) -> Self {
+                $name { stored: if ByteOrder::native() == $order { value } else { value.swap_bytes() } }
+            }
+
+            pub fn get(&self) -> T {
+                if ByteOrder::native() == $order { self.stored } else { self.stored.swap_bytes() }
+            }
+        }
+
+        unsafe impl<T: Endian> Plain for $name<T> {}
+
+        impl<T: Endian + fmt::Debug> fmt::Debug for $name<T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.debug_tuple(stringify!($name)).field(&self.get()).finish()
+            }
+        }
+    };
+}
+
+endian_wrapper!(Le, ByteOrder::Little, "`T` stored little-endian regardless of the host byte order.");
+endian_wrapper!(Be, ByteOrder::Big, "`T` stored big-endian regardless of the host byte order.");
+
+/// Stores `Endian` scalars in the given byte order, so `read_endian` and `write_endian`
+/// behave like on the emulated CPU.
+///
+/// Only those swap bytes: `read`, `write` and the other `Memory` accesses pass values through unchanged,
+/// as a struct or an array can't be byte-reversed as a whole. Use `Le` and `Be` fields for them.
+pub struct EndianMemory<MEM> {
+    memory: MEM,
+    order: ByteOrder,
+}
+
+impl<MEM> EndianMemory<MEM> {
+    pub fn new(memory: MEM, order: ByteOrder) -> Self { EndianMemory { memory, order } }
+
+    pub fn order(&self) -> ByteOrder { self.order }
+    pub fn inner(&self) -> &MEM { &self.memory }
+    pub fn inner_mut(&mut self) -> &mut MEM { &mut self.memory }
+    pub fn into_inner(self) -> MEM { self.memory }
+
+    fn swapped<T: Endian>(&self, value: T) -> T {
+        if self.order != ByteOrder::native() { value.swap_bytes() } else { value }
+    }
+
+    /// # Safety
+    /// Same as `Memory::read`.
+    pub unsafe fn read_endian<PTR: Copy, T: Endian>(&self, ptr: PTR) -> T where MEM: Memory<PTR> {
+        self.swapped(self.memory.read(ptr))
+    }
+
+    /// # Safety
+    /// Same as `Memory::write`.
+    pub unsafe fn write_endian<PTR: Copy, T: Endian>(&mut self, ptr: PTR, value: T) where MEM: Memory<PTR> {
+        let value = self.swapped(value);
+        self.memory.write(ptr, value)
+    }
+
+    /// # Safety
+    /// Same as `Memory::try_read`.
+    pub unsafe fn try_read_endian<PTR: Copy, T: Endian>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> where MEM: Memory<PTR> {
+        self.memory.try_read(ptr).map(|value| self.swapped(value))
+    }
+
+    /// # Safety
+    /// Same as `Memory::try_write`.
+    pub unsafe fn try_write_endian<PTR: Copy, T: Endian>(&mut self, ptr: PTR, value: T) -> Result<(), MemoryError<PTR>> where MEM: Memory<PTR> {
+        let value = self.swapped(value);
+        self.memory.try_write(ptr, value)
+    }
+}
+
+impl<PTR: Copy, MEM: Memory<PTR>> Memory<PTR> for EndianMemory<MEM> {
+    unsafe fn read<T>(&self, ptr: PTR) -> T { self.memory.read(ptr) }
+    unsafe fn write<T>(&mut self, ptr: PTR, value: T) { self.memory.write(ptr, value) }
+
+    unsafe fn try_read<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> { self.memory.try_read(ptr) }
+    unsafe fn try_write<T>(&mut self, ptr: PTR, value: T) -> Result<(), MemoryError<PTR>> { self.memory.try_write(ptr, value) }
+    unsafe fn try_fetch<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> { self.memory.try_fetch(ptr) }
+
+    // bytes have no byte order, so bulk operations go straight to the inner memory
+
+    unsafe fn read_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        self.memory.read_bytes(ptr, buffer)
+    }
+
+    unsafe fn fetch_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        self.memory.fetch_bytes(ptr, buffer)
+    }
+
+    unsafe fn write_bytes(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        self.memory.write_bytes(ptr, bytes)
+    }
+
+    unsafe fn fill(&mut self, ptr: PTR, len: usize, value: u8) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        self.memory.fill(ptr, len, value)
+    }
+
+    unsafe fn copy_within(&mut self, from: PTR, to: PTR, len: usize) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        self.memory.copy_within(from, to, len)
+    }
+
+    unsafe fn compare(&self, a: PTR, b: PTR, len: usize) -> Result<Ordering, MemoryError<PTR>> where PTR: Pointer {
+        self.memory.compare(a, b, len)
+    }
+
+    unsafe fn copy_to<OTHER>(&self, from: PTR, other: &mut OTHER, to: PTR, len: usize) -> Result<(), MemoryError<PTR>>
+        where PTR: Pointer, OTHER: Memory<PTR> + ?Sized
+    {
+        self.memory.copy_to(from, other, to, len)
+    }
+
+    fn allocated(&mut self, ptr: PTR, size: usize) { self.memory.allocated(ptr, size) }
+    fn deallocated(&mut self, ptr: PTR, size: usize) { self.memory.deallocated(ptr, size) }
+
+    unsafe fn read_metadata(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        self.memory.read_metadata(ptr, buffer)
+    }
+
+    unsafe fn write_metadata(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        self.memory.write_metadata(ptr, bytes)
+    }
+}
+
+impl<T: Endian, PTR: Pointer> TypedPtr<T, PTR> {
+    /// Reads the scalar in the byte order of `mem`.
+    ///
+    /// # Safety
+    /// Same as `read`.
+    pub unsafe fn read_endian<MEM: Memory<PTR>>(&self, mem: &EndianMemory<MEM>) -> T { mem.read_endian(self.address()) }
+
+    /// Writes the scalar in the byte order of `mem`.
+    ///
+    /// # Safety
+    /// Same as `write`.
+    pub unsafe fn write_endian<MEM: Memory<PTR>>(&self, mem: &mut EndianMemory<MEM>, value: T) { mem.write_endian(self.address(), value) }
+}
+
+unsafe impl<PTR: Copy, MEM: CheckedMemory<PTR>> CheckedMemory<PTR> for EndianMemory<MEM> {}
+
+macro_rules! byte_order_accessors {
+    ($($t:ty => $read_le:ident, $read_be:ident, $write_le:ident, $write_be:ident;)*) => {
+        /// Reads and writes integers in an explicit byte order, one byte at a time,
+        /// so the result does not depend on the byte order of the memory itself.
+        pub trait ByteOrderAccess<PTR: Pointer>: CheckedMemory<PTR> {
+            $(
+                fn $read_le(&self, ptr: PTR) -> $t { <$t>::from_le_bytes(read_byte_array(self, ptr)) }
+                fn $read_be(&self, ptr: PTR) -> $t { <$t>::from_be_bytes(read_byte_array(self, ptr)) }
+                fn $write_le(&mut self, ptr: PTR, value: $t) { write_byte_array(self, ptr, value.to_le_bytes()) }
+                fn $write_be(&mut self, ptr: PTR, value: $t) { write_byte_array(self, ptr, value.to_be_bytes()) }
+            )*
+        }
+    };
+}
+
+byte_order_accessors! {
+    u16 => read_u16_le, read_u16_be, write_u16_le, write_u16_be;
+    u32 => read_u32_le, read_u32_be, write_u32_le, write_u32_be;
+    u64 => read_u64_le, read_u64_be, write_u64_le, write_u64_be;
+}
+
+impl<PTR: Pointer, MEM: CheckedMemory<PTR>> ByteOrderAccess<PTR> for MEM {}
+
+fn read_byte_array<PTR: Pointer, MEM: CheckedMemory<PTR> + ?Sized, const N: usize>(memory: &MEM, ptr: PTR) -> [u8; N] {
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = memory.read_plain(byte_address(ptr, i));
+    }
+    bytes
+}
+
+fn write_byte_array<PTR: Pointer, MEM: CheckedMemory<PTR> + ?Sized, const N: usize>(memory: &mut MEM, ptr: PTR, bytes: [u8; N]) {
+    for (i, &byte) in bytes.iter().enumerate() {
+        memory.write_plain(byte_address(ptr, i), byte);
+    }
+}
+
+fn byte_address<PTR: Pointer>(ptr: PTR, index: usize) -> PTR {
+    ptr.checked_add(PTR::from_usize(index)).expect("access past the max pointer value")
+}
@@ -0,0 +1,41 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+use memmap2::MmapMut;
+
+use pointer::Pointer;
+use region::MemoryRegion;
+
+/// `MemoryRegion`, whose bytes live in a memory-mapped file,
+/// so its contents persist across runs.
+pub type FileRegion<PTR> = MemoryRegion<PTR, MmapMut>;
+
+impl<PTR: Pointer> MemoryRegion<PTR, MmapMut> {
+    /// Maps `path`, replacing its contents with `max` zero bytes.
+    pub fn create<P: AsRef<Path>>(path: P, max: PTR) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        FileRegion::map(file, max)
+    }
+
+    /// Maps `path`, keeping its contents. A missing file is created,
+    /// and a file shorter than `max` bytes is extended with zeroes.
+    pub fn open<P: AsRef<Path>>(path: P, max: PTR) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        FileRegion::map(file, max)
+    }
+
+    fn map(file: File, max: PTR) -> io::Result<Self> {
+        let len = max.to_usize() as u64;
+        if file.metadata()?.len() < len {
+            file.set_len(len)?;
+        }
+        let map = unsafe { memmap2::MmapOptions::new().len(max.to_usize()).map_mut(&file)? };
+        Ok(MemoryRegion::from_storage(map))
+    }
+
+    /// Writes modified pages to the file and waits for it.
+    pub fn flush(&self) -> io::Result<()> { self.storage().flush() }
+    /// Starts writing modified pages to the file without waiting.
+    pub fn flush_async(&self) -> io::Result<()> { self.storage().flush_async() }
+}
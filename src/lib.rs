@@ -2,17 +2,61 @@
 
 #[macro_use]
 extern crate lazy_static;
+extern crate mem_bitness_derive;
+extern crate memmap2;
+
+// lets `#[derive(Pointer)]` refer to `::mem_bitness` from within this crate
+extern crate self as mem_bitness;
 
 pub mod alloc;
+mod compressed;
+mod device;
+mod endian;
+mod file_region;
 mod memory;
+mod memory_map;
+mod non_null;
+mod plain;
+mod pointer;
+mod protected;
 mod region;
+mod rel_ptr;
 mod rust_mem;
+mod segmented;
+mod shadow;
+mod sparse;
+mod storage;
+mod storable;
+mod tagged_ptr;
+mod tracing;
+mod transaction;
 mod typed_ptr;
+mod uint;
 
-pub use self::memory::Memory;
-pub use self::rust_mem::RUST_MEMORY;
-pub use self::region::MemoryRegion;
+pub use mem_bitness_derive::{Plain, Pointer, Storable};
+pub use self::compressed::{CompressedMemory, CompressedPtr};
+pub use self::device::Device;
+pub use self::endian::{Be, ByteOrder, ByteOrderAccess, Endian, EndianMemory, Le};
+pub use self::file_region::FileRegion;
+pub use self::memory::{Access, Memory, MemoryError, MemoryErrorKind};
+pub use self::memory_map::{Backend, MemoryMap};
+pub use self::non_null::{AllOnes, NonNullPtr, Null, Sentinel, Zero};
+pub use self::plain::{CheckedMemory, Plain};
+pub use self::pointer::{OverflowErr, Pointer};
+pub use self::protected::{Permissions, Protected};
+pub use self::rust_mem::{RustMemory, RUST_MEMORY};
+pub use self::region::{AlignmentPolicy, MemoryRegion};
+pub use self::rel_ptr::{Offset, RelPtr};
+pub use self::segmented::{SegmentedMemory, SegmentedPtr};
+pub use self::shadow::{Allocation, Report, Sanitizer, ShadowMap, ShadowState, Violation};
+pub use self::sparse::{Pages, Snapshot, SparseRegion};
+pub use self::storable::Storable;
+pub use self::storage::Storage;
+pub use self::tagged_ptr::TaggedPtr;
+pub use self::tracing::{read_trace, replay, TraceEvent, TraceRing, TraceSink, TraceWriter, TracingMemory};
+pub use self::transaction::{Savepoint, Transaction};
 pub use self::typed_ptr::TypedPtr;
+pub use self::uint::{BitWidth, Bits, UInt};
 
 #[cfg(test)]
 mod tests;
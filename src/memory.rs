@@ -1,4 +1,324 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+
+use plain::{CheckedMemory, Plain};
+use pointer::Pointer;
+use storable::Storable;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryErrorKind {
+    OutOfBounds,
+    Misaligned,
+    PermissionDenied,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    /// Instruction fetch, see `Memory::try_fetch`.
+    Execute,
+}
+
+/// Fault raised by a failed `try_read` or `try_write`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryError<PTR> {
+    kind: MemoryErrorKind,
+    access: Access,
+    address: PTR,
+    size: usize,
+}
+
+impl<PTR: Copy> MemoryError<PTR> {
+    pub fn new(kind: MemoryErrorKind, access: Access, address: PTR, size: usize) -> Self {
+        MemoryError { kind, access, address, size }
+    }
+
+    pub fn kind(&self) -> MemoryErrorKind { self.kind }
+    pub fn access(&self) -> Access { self.access }
+    /// Address of the faulting access.
+    pub fn address(&self) -> PTR { self.address }
+    /// Size of the faulting access in bytes.
+    pub fn size(&self) -> usize { self.size }
+
+    /// The same fault, reported at an address of another address space.
+    pub fn at<P: Copy>(&self, address: P) -> MemoryError<P> {
+        MemoryError { kind: self.kind, access: self.access, address, size: self.size }
+    }
+}
+
+impl<PTR: fmt::Debug> fmt::Display for MemoryError<PTR> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            MemoryErrorKind::OutOfBounds => "out of bounds",
+            MemoryErrorKind::Misaligned => "misaligned",
+            MemoryErrorKind::PermissionDenied => "permission denied",
+        };
+        let access = match self.access {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::Execute => "execute",
+        };
+        write!(f, "{} {} of {} bytes at {:?}", kind, access, self.size, self.address)
+    }
+}
+
+impl<PTR: fmt::Debug> Error for MemoryError<PTR> {}
+
 pub trait Memory<PTR: Copy> {
     unsafe fn read<T>(&self, ptr: PTR) -> T;
     unsafe fn write<T>(&mut self, ptr: PTR, value: T);
-}
\ No newline at end of file
+
+    /// Like `read`, but reports faults instead of panicking or causing UB.
+    /// The default implementation can't detect any and always calls `read`.
+    ///
+    /// # Safety
+    /// The bytes at `ptr`, if there are any, have to be a valid `T`.
+    unsafe fn try_read<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> { Ok(self.read(ptr)) }
+    /// Like `write`, but reports faults instead of panicking or causing UB.
+    /// The default implementation can't detect any and always calls `write`.
+    ///
+    /// # Safety
+    /// Nothing else may rely on the overwritten bytes.
+    unsafe fn try_write<T>(&mut self, ptr: PTR, value: T) -> Result<(), MemoryError<PTR>> {
+        self.write(ptr, value);
+        Ok(())
+    }
+
+    /// Reads code at `ptr` for execution, which memories with permissions check separately from reads.
+    /// The default implementation doesn't distinguish the two and calls `try_read`.
+    ///
+    /// # Safety
+    /// Same as `try_read`.
+    unsafe fn try_fetch<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> { self.try_read(ptr) }
+
+    fn read_plain<T: Plain>(&self, ptr: PTR) -> T where Self: CheckedMemory<PTR> {
+        unsafe { self.read(ptr) }
+    }
+    fn write_plain<T: Plain>(&mut self, ptr: PTR, value: T) where Self: CheckedMemory<PTR> {
+        unsafe { self.write(ptr, value) }
+    }
+    fn try_read_plain<T: Plain>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> where Self: CheckedMemory<PTR> {
+        unsafe { self.try_read(ptr) }
+    }
+    fn try_write_plain<T: Plain>(&mut self, ptr: PTR, value: T) -> Result<(), MemoryError<PTR>> where Self: CheckedMemory<PTR> {
+        unsafe { self.try_write(ptr, value) }
+    }
+
+    /// Fills `buffer` with the bytes starting at `ptr`.
+    ///
+    /// # Safety
+    /// Same as `read` for each of the bytes.
+    unsafe fn read_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        let len = buffer.len();
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = self.try_read(byte_at(ptr, i, len, Access::Read)?)?;
+        }
+        Ok(())
+    }
+
+    /// Like `read_bytes`, but fetches the bytes for execution, see `try_fetch`.
+    ///
+    /// # Safety
+    /// Same as `read_bytes`.
+    unsafe fn fetch_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        let len = buffer.len();
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = self.try_fetch(byte_at(ptr, i, len, Access::Execute)?)?;
+        }
+        Ok(())
+    }
+
+    /// # Safety
+    /// Same as `write` for each of the bytes.
+    unsafe fn write_bytes(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.try_write(byte_at(ptr, i, bytes.len(), Access::Write)?, byte)?;
+        }
+        Ok(())
+    }
+
+    /// Sets `len` bytes starting at `ptr` to `value`, like `memset`.
+    ///
+    /// # Safety
+    /// Same as `write` for each of the bytes.
+    unsafe fn fill(&mut self, ptr: PTR, len: usize, value: u8) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        for i in 0..len {
+            self.try_write(byte_at(ptr, i, len, Access::Write)?, value)?;
+        }
+        Ok(())
+    }
+
+    /// Copies `len` bytes from `from` to `to`, like `memmove`: the ranges may overlap.
+    ///
+    /// # Safety
+    /// Same as `read` for each of the source bytes and `write` for each of the destination bytes.
+    unsafe fn copy_within(&mut self, from: PTR, to: PTR, len: usize) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        let mut buffer = vec![0; len];
+        self.read_bytes(from, &mut buffer)?;
+        self.write_bytes(to, &buffer)
+    }
+
+    /// Lexicographically compares `len` bytes at `a` with `len` bytes at `b`, like `memcmp`.
+    ///
+    /// # Safety
+    /// Same as `read` for each of the bytes.
+    unsafe fn compare(&self, a: PTR, b: PTR, len: usize) -> Result<Ordering, MemoryError<PTR>> where PTR: Pointer {
+        for i in 0..len {
+            let left: u8 = self.try_read(byte_at(a, i, len, Access::Read)?)?;
+            let right: u8 = self.try_read(byte_at(b, i, len, Access::Read)?)?;
+            if left != right {
+                return Ok(left.cmp(&right));
+            }
+        }
+        Ok(Ordering::Equal)
+    }
+
+    /// Copies `len` bytes at `from` to `to` in another memory.
+    /// Faults in this memory are reported as reads, and faults in `other` as writes.
+    ///
+    /// # Safety
+    /// Same as `read` for each of the source bytes and `write` for each of the destination bytes.
+    unsafe fn copy_to<OTHER>(&self, from: PTR, other: &mut OTHER, to: PTR, len: usize) -> Result<(), MemoryError<PTR>>
+        where PTR: Pointer, OTHER: Memory<PTR> + ?Sized
+    {
+        let mut buffer = vec![0; len];
+        self.read_bytes(from, &mut buffer)?;
+        other.write_bytes(to, &buffer)
+    }
+
+    /// Reads the `Storable` layout of `T` at `ptr`.
+    ///
+    /// # Safety
+    /// Same as `read_bytes`.
+    unsafe fn load<T: Storable>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> where PTR: Pointer {
+        let mut small = [0; SMALL_VALUE];
+        let mut large = Vec::new();
+        let buffer = value_buffer::<T>(&mut small, &mut large);
+        self.read_bytes(ptr, buffer)?;
+        Ok(T::load(buffer))
+    }
+
+    /// Writes `value` at `ptr` in the `Storable` layout of `T`.
+    ///
+    /// # Safety
+    /// Same as `write_bytes`.
+    unsafe fn store<T: Storable>(&mut self, ptr: PTR, value: &T) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        let mut small = [0; SMALL_VALUE];
+        let mut large = Vec::new();
+        let buffer = value_buffer::<T>(&mut small, &mut large);
+        value.store(buffer);
+        self.write_bytes(ptr, buffer)
+    }
+
+    /// Called by allocators after handing out `size` bytes at `ptr`. Does nothing by default.
+    fn allocated(&mut self, _ptr: PTR, _size: usize) {}
+    /// Called by allocators before taking back `size` bytes at `ptr`. Does nothing by default.
+    fn deallocated(&mut self, _ptr: PTR, _size: usize) {}
+
+    /// `read_bytes` for allocator bookkeeping and other tools, that memories checking program accesses let through.
+    ///
+    /// # Safety
+    /// Same as `read_bytes`.
+    unsafe fn read_metadata(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        self.read_bytes(ptr, buffer)
+    }
+    /// `write_bytes` for allocator bookkeeping and other tools, that memories checking program accesses let through.
+    ///
+    /// # Safety
+    /// Same as `write_bytes`.
+    unsafe fn write_metadata(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        self.write_bytes(ptr, bytes)
+    }
+
+    /// `load` through `read_metadata`.
+    ///
+    /// # Safety
+    /// Same as `read_metadata`.
+    unsafe fn load_metadata<T: Storable>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> where PTR: Pointer {
+        let mut small = [0; SMALL_VALUE];
+        let mut large = Vec::new();
+        let buffer = value_buffer::<T>(&mut small, &mut large);
+        self.read_metadata(ptr, buffer)?;
+        Ok(T::load(buffer))
+    }
+    /// `store` through `write_metadata`.
+    ///
+    /// # Safety
+    /// Same as `write_metadata`.
+    unsafe fn store_metadata<T: Storable>(&mut self, ptr: PTR, value: &T) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        let mut small = [0; SMALL_VALUE];
+        let mut large = Vec::new();
+        let buffer = value_buffer::<T>(&mut small, &mut large);
+        value.store(buffer);
+        self.write_metadata(ptr, buffer)
+    }
+}
+
+/// Panics with a description of `error`, for the panicking `read` and `write`.
+pub fn fault<PTR: Pointer>(error: MemoryError<PTR>) -> ! {
+    panic!("{:?} {:?} of {} bytes at {:#x}", error.kind(), error.access(), error.size(), error.address().to_usize())
+}
+
+/// Lets functions, that take a `Memory` by value, such as adapters, borrow one instead.
+impl<PTR: Copy, M: Memory<PTR> + ?Sized> Memory<PTR> for &mut M {
+    unsafe fn read<T>(&self, ptr: PTR) -> T { (**self).read(ptr) }
+    unsafe fn write<T>(&mut self, ptr: PTR, value: T) { (**self).write(ptr, value) }
+    unsafe fn try_read<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> { (**self).try_read(ptr) }
+    unsafe fn try_write<T>(&mut self, ptr: PTR, value: T) -> Result<(), MemoryError<PTR>> { (**self).try_write(ptr, value) }
+    unsafe fn try_fetch<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> { (**self).try_fetch(ptr) }
+
+    unsafe fn read_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        (**self).read_bytes(ptr, buffer)
+    }
+    unsafe fn fetch_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        (**self).fetch_bytes(ptr, buffer)
+    }
+    unsafe fn write_bytes(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        (**self).write_bytes(ptr, bytes)
+    }
+    unsafe fn fill(&mut self, ptr: PTR, len: usize, value: u8) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        (**self).fill(ptr, len, value)
+    }
+    unsafe fn copy_within(&mut self, from: PTR, to: PTR, len: usize) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        (**self).copy_within(from, to, len)
+    }
+    unsafe fn compare(&self, a: PTR, b: PTR, len: usize) -> Result<Ordering, MemoryError<PTR>> where PTR: Pointer {
+        (**self).compare(a, b, len)
+    }
+    unsafe fn copy_to<OTHER>(&self, from: PTR, other: &mut OTHER, to: PTR, len: usize) -> Result<(), MemoryError<PTR>>
+        where PTR: Pointer, OTHER: Memory<PTR> + ?Sized
+    {
+        (**self).copy_to(from, other, to, len)
+    }
+
+    fn allocated(&mut self, ptr: PTR, size: usize) { (**self).allocated(ptr, size) }
+    fn deallocated(&mut self, ptr: PTR, size: usize) { (**self).deallocated(ptr, size) }
+    unsafe fn read_metadata(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        (**self).read_metadata(ptr, buffer)
+    }
+    unsafe fn write_metadata(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> where PTR: Pointer {
+        (**self).write_metadata(ptr, bytes)
+    }
+}
+
+const SMALL_VALUE: usize = 64;
+
+/// Buffer for a `Storable` value, that only allocates for large ones.
+fn value_buffer<'a, T: Storable>(small: &'a mut [u8; SMALL_VALUE], large: &'a mut Vec<u8>) -> &'a mut [u8] {
+    if T::SIZE <= SMALL_VALUE {
+        &mut small[..T::SIZE]
+    } else {
+        large.resize(T::SIZE, 0);
+        large
+    }
+}
+
+/// Address of the `index`-th byte of a `len` bytes long access at `ptr`.
+fn byte_at<PTR: Pointer>(ptr: PTR, index: usize, len: usize, access: Access) -> Result<PTR, MemoryError<PTR>> {
+    let offset = PTR::from_usize(index);
+    Some(offset).filter(|offset| offset.to_usize() == index)
+        .and_then(|offset| ptr.checked_add(offset))
+        .ok_or_else(|| MemoryError::new(MemoryErrorKind::OutOfBounds, access, ptr, len))
+}
@@ -0,0 +1,292 @@
+use std::cmp;
+use std::mem;
+use std::slice;
+
+use device::{Device, Mounted};
+use memory::{fault, Access, Memory, MemoryError, MemoryErrorKind};
+use plain::CheckedMemory;
+use pointer::Pointer;
+
+/// Byte-level access to something mapped into a `MemoryMap`.
+/// Implemented for every `CheckedMemory`; unlike `Memory` it can be used as a trait object.
+pub trait Backend<PTR> {
+    fn read_into(&self, offset: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>>;
+    /// Like `read_into`, but for instruction fetches, see `Memory::try_fetch`.
+    fn fetch_into(&self, offset: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>>;
+    fn write_from(&mut self, offset: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>>;
+
+    /// Like `read_into` and `write_from`, but for allocator bookkeeping, see `Memory::read_metadata`.
+    fn read_metadata_into(&self, offset: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>>;
+    fn write_metadata_from(&mut self, offset: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>>;
+
+    /// `Memory::allocated` and `Memory::deallocated` at `offset`.
+    fn allocated_at(&mut self, offset: PTR, size: usize);
+    fn deallocated_at(&mut self, offset: PTR, size: usize);
+}
+
+impl<PTR: Pointer, M: CheckedMemory<PTR>> Backend<PTR> for M {
+    fn read_into(&self, offset: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        unsafe { self.read_bytes(offset, buffer) }
+    }
+    fn fetch_into(&self, offset: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        unsafe { self.fetch_bytes(offset, buffer) }
+    }
+    fn write_from(&mut self, offset: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> {
+        unsafe { self.write_bytes(offset, bytes) }
+    }
+    fn read_metadata_into(&self, offset: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        unsafe { self.read_metadata(offset, buffer) }
+    }
+    fn write_metadata_from(&mut self, offset: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> {
+        unsafe { self.write_metadata(offset, bytes) }
+    }
+    fn allocated_at(&mut self, offset: PTR, size: usize) { self.allocated(offset, size) }
+    fn deallocated_at(&mut self, offset: PTR, size: usize) { self.deallocated(offset, size) }
+}
+
+type ReadOp<PTR> = fn(&dyn Backend<PTR>, PTR, &mut [u8]) -> Result<(), MemoryError<PTR>>;
+type WriteOp<PTR> = fn(&mut dyn Backend<PTR>, PTR, &[u8]) -> Result<(), MemoryError<PTR>>;
+
+enum Target<PTR> {
+    Backend(usize),
+    Mirror { of: PTR, period: usize },
+}
+
+struct Mapping<PTR> {
+    start: PTR,
+    last: PTR,
+    target: Target<PTR>,
+}
+
+/// Part of an access, that goes to a single place.
+enum Piece<PTR> {
+    Backend { index: usize, offset: PTR },
+    OpenBus,
+}
+
+// mirrors of mirrors are fine, but a mirror of itself would never resolve
+const MAX_MIRROR_DEPTH: usize = 8;
+
+/// Address space, that dispatches every access to the backend mapped at its address.
+///
+/// Backends see offsets from the start of their mapping. Mappings may overlap, in which case
+/// the one mapped last wins, so I/O windows can be put on top of RAM. Accesses, that straddle
+/// mappings, are split. Unmapped addresses read as the open bus value and ignore writes,
+/// or fail with `OutOfBounds`, if there's no open bus value (the default).
+pub struct MemoryMap<'a, PTR: Pointer> {
+    mappings: Vec<Mapping<PTR>>,
+    backends: Vec<Box<dyn Backend<PTR> + 'a>>,
+    open_bus: Option<u8>,
+}
+
+impl<'a, PTR: Pointer> MemoryMap<'a, PTR> {
+    pub fn new() -> Self { MemoryMap { mappings: Vec::new(), backends: Vec::new(), open_bus: None } }
+
+    pub fn open_bus(&self) -> Option<u8> { self.open_bus }
+    pub fn set_open_bus(&mut self, value: Option<u8>) { self.open_bus = value }
+
+    /// Maps addresses from `start` to `last` inclusive to `backend`, at offsets from 0.
+    pub fn map<B: Backend<PTR> + 'a>(&mut self, start: PTR, last: PTR, backend: B) {
+        if start > last {
+            panic!("mapping ends before it starts")
+        }
+        self.backends.push(Box::new(backend));
+        let index = self.backends.len() - 1;
+        self.mappings.push(Mapping { start, last, target: Target::Backend(index) });
+    }
+
+    /// Maps addresses from `start` to `last` inclusive to `device`, at offsets from 0.
+    /// Like any mapping, a device can be mounted over a part of a region mapped before it:
+    /// a `MemoryRegion` has no `mount` of its own, so wrap it into a `MemoryMap` first.
+    pub fn mount<D: Device<PTR> + 'a>(&mut self, start: PTR, last: PTR, device: D) {
+        self.map(start, last, Mounted::new(device))
+    }
+
+    /// Makes addresses from `start` to `last` inclusive repeat the `period` bytes at `of`.
+    pub fn mirror(&mut self, start: PTR, last: PTR, of: PTR, period: usize) {
+        if start > last || period == 0 {
+            panic!("empty mirror")
+        }
+        self.mappings.push(Mapping { start, last, target: Target::Mirror { of, period } });
+    }
+
+    /// Reads with `read` from every backend in the way, reporting faults as `access`.
+    fn read_into(&self, address: PTR, buffer: &mut [u8], access: Access, read: ReadOp<PTR>) -> Result<(), MemoryError<PTR>> {
+        let backends = &self.backends;
+        let open_bus = self.open_bus;
+        route(&self.mappings, address, buffer.len(), access, 0, &mut |piece, bus, done, len| {
+            let target = &mut buffer[done..done + len];
+            match piece {
+                Piece::Backend { index, offset } => read(&*backends[index], offset, target)
+                    .map_err(|error| MemoryError::new(error.kind(), access, bus, len)),
+                Piece::OpenBus => match open_bus {
+                    Some(value) => {
+                        target.fill(value);
+                        Ok(())
+                    },
+                    None => Err(MemoryError::new(MemoryErrorKind::OutOfBounds, access, bus, len)),
+                },
+            }
+        })
+    }
+
+    fn write_from(&mut self, address: PTR, bytes: &[u8], write: WriteOp<PTR>) -> Result<(), MemoryError<PTR>> {
+        let backends = &mut self.backends;
+        let open_bus = self.open_bus;
+        route(&self.mappings, address, bytes.len(), Access::Write, 0, &mut |piece, bus, done, len| {
+            let source = &bytes[done..done + len];
+            match piece {
+                Piece::Backend { index, offset } => write(&mut *backends[index], offset, source)
+                    .map_err(|error| MemoryError::new(error.kind(), Access::Write, bus, len)),
+                Piece::OpenBus => match open_bus {
+                    Some(_) => Ok(()),
+                    None => Err(MemoryError::new(MemoryErrorKind::OutOfBounds, Access::Write, bus, len)),
+                },
+            }
+        })
+    }
+
+    /// Passes an allocator hook on to the backends under `size` bytes at `address`.
+    fn notify(&mut self, address: PTR, size: usize, hook: fn(&mut dyn Backend<PTR>, PTR, usize)) {
+        let backends = &mut self.backends;
+        // hooks can't fail, so unmapped parts are skipped
+        let _ = route(&self.mappings, address, size, Access::Write, 0, &mut |piece, _, _, len| {
+            if let Piece::Backend { index, offset } = piece {
+                hook(&mut *backends[index], offset, len)
+            }
+            Ok(())
+        });
+    }
+}
+
+impl<'a, PTR: Pointer> Default for MemoryMap<'a, PTR> {
+    fn default() -> Self { MemoryMap::new() }
+}
+
+fn offset<PTR: Pointer>(address: PTR, distance: usize) -> Option<PTR> {
+    let distance_ptr = PTR::from_usize(distance);
+    if distance_ptr.to_usize() != distance {
+        return None;
+    }
+    address.checked_add(distance_ptr)
+}
+
+/// Splits an access of `len` bytes at `address` into pieces, and calls `f` with each of them,
+/// its bus address, its offset from the start of the access, and its length.
+fn route<PTR: Pointer>(
+    mappings: &[Mapping<PTR>], address: PTR, len: usize, access: Access, depth: usize,
+    f: &mut dyn FnMut(Piece<PTR>, PTR, usize, usize) -> Result<(), MemoryError<PTR>>,
+) -> Result<(), MemoryError<PTR>> {
+    let mut done = 0;
+    while done < len {
+        let bus = offset(address, done)
+            .ok_or_else(|| MemoryError::new(MemoryErrorKind::OutOfBounds, access, address, len))?;
+        let remaining = len - done;
+        let top = mappings.iter().enumerate().rev().find(|&(_, mapping)| mapping.start <= bus && bus <= mapping.last);
+        let (index, mapping) = match top {
+            Some(top) => top,
+            None => {
+                let gap = mappings.iter()
+                    .filter(|mapping| mapping.start > bus)
+                    .map(|mapping| (mapping.start - bus).to_usize())
+                    .fold(remaining, cmp::min);
+                f(Piece::OpenBus, bus, done, gap)?;
+                done += gap;
+                continue;
+            },
+        };
+
+        let chunk = mappings[index + 1..].iter()
+            .filter(|later| later.start > bus && later.start <= mapping.last)
+            .map(|later| (later.start - bus).to_usize())
+            .fold(cmp::min(remaining, (mapping.last - bus).to_usize().saturating_add(1)), cmp::min);
+        let within = bus - mapping.start;
+        match mapping.target {
+            Target::Backend(backend) => f(Piece::Backend { index: backend, offset: within }, bus, done, chunk)?,
+            Target::Mirror { of, period } => {
+                if depth == MAX_MIRROR_DEPTH {
+                    panic!("mirror refers to itself")
+                }
+                let rel = within.to_usize() % period;
+                let chunk = cmp::min(chunk, period - rel);
+                let mirrored = offset(of, rel)
+                    .ok_or_else(|| MemoryError::new(MemoryErrorKind::OutOfBounds, access, bus, chunk))?;
+                route(mappings, mirrored, chunk, access, depth + 1, &mut |piece, _, inner_done, inner_len| {
+                    let inner_bus = offset(bus, inner_done).expect("mirror piece past the end of the bus");
+                    f(piece, inner_bus, done + inner_done, inner_len)
+                })?;
+                done += chunk;
+                continue;
+            },
+        }
+        done += chunk;
+    }
+    Ok(())
+}
+
+impl<'a, PTR: Pointer> Memory<PTR> for MemoryMap<'a, PTR> {
+    /// Panics on unmapped addresses without an open bus value, and on backend faults.
+    unsafe fn read<T>(&self, ptr: PTR) -> T {
+        match self.try_read(ptr) {
+            Ok(value) => value,
+            Err(error) => fault(error),
+        }
+    }
+
+    /// Panics on unmapped addresses without an open bus value, and on backend faults.
+    unsafe fn write<T>(&mut self, ptr: PTR, value: T) {
+        if let Err(error) = self.try_write(ptr, value) {
+            fault(error)
+        }
+    }
+
+    unsafe fn try_read<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> {
+        let mut value = mem::MaybeUninit::<T>::zeroed();
+        let bytes = slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, mem::size_of::<T>());
+        self.read_into(ptr, bytes, Access::Read, |backend, offset, buffer| backend.read_into(offset, buffer))?;
+        Ok(value.assume_init())
+    }
+
+    unsafe fn try_fetch<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> {
+        let mut value = mem::MaybeUninit::<T>::zeroed();
+        let bytes = slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, mem::size_of::<T>());
+        self.read_into(ptr, bytes, Access::Execute, |backend, offset, buffer| backend.fetch_into(offset, buffer))?;
+        Ok(value.assume_init())
+    }
+
+    unsafe fn try_write<T>(&mut self, ptr: PTR, value: T) -> Result<(), MemoryError<PTR>> {
+        let value = mem::ManuallyDrop::new(value);
+        let bytes = slice::from_raw_parts(&*value as *const T as *const u8, mem::size_of::<T>());
+        self.write_bytes(ptr, bytes)
+    }
+
+    unsafe fn read_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.read_into(ptr, buffer, Access::Read, |backend, offset, buffer| backend.read_into(offset, buffer))
+    }
+
+    unsafe fn fetch_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.read_into(ptr, buffer, Access::Execute, |backend, offset, buffer| backend.fetch_into(offset, buffer))
+    }
+
+    unsafe fn write_bytes(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> {
+        self.write_from(ptr, bytes, |backend, offset, bytes| backend.write_from(offset, bytes))
+    }
+
+    fn allocated(&mut self, ptr: PTR, size: usize) {
+        self.notify(ptr, size, |backend, offset, size| backend.allocated_at(offset, size))
+    }
+
+    fn deallocated(&mut self, ptr: PTR, size: usize) {
+        self.notify(ptr, size, |backend, offset, size| backend.deallocated_at(offset, size))
+    }
+
+    unsafe fn read_metadata(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.read_into(ptr, buffer, Access::Read, |backend, offset, buffer| backend.read_metadata_into(offset, buffer))
+    }
+
+    unsafe fn write_metadata(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> {
+        self.write_from(ptr, bytes, |backend, offset, bytes| backend.write_metadata_from(offset, bytes))
+    }
+}
+
+unsafe impl<'a, PTR: Pointer> CheckedMemory<PTR> for MemoryMap<'a, PTR> {}
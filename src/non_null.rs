@@ -0,0 +1,72 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use memory::Memory;
+use pointer::Pointer;
+use typed_ptr::TypedPtr;
+
+/// Selects which address `NonNullPtr` treats as null.
+pub trait Null {
+    fn null<PTR: Pointer>() -> PTR;
+}
+
+pub struct Zero;
+pub struct AllOnes;
+pub struct Sentinel<const ADDRESS: usize>;
+
+impl Null for Zero {
+    fn null<PTR: Pointer>() -> PTR { PTR::from_usize(0) }
+}
+
+impl Null for AllOnes {
+    fn null<PTR: Pointer>() -> PTR { PTR::max_value() }
+}
+
+impl<const ADDRESS: usize> Null for Sentinel<ADDRESS> {
+    fn null<PTR: Pointer>() -> PTR { PTR::from_usize(ADDRESS) }
+}
+
+/// `TypedPtr`, that is never null, so `Option<NonNullPtr<T, PTR, NULL>>` is a nullable pointer.
+///
+/// The address is stored XOR-ed with the null address, which moves null to the zero niche:
+/// for primitive integer pointers, `SegmentedPtr`, `CompressedPtr` and pointers derived from them
+/// the `Option` has the same size as `PTR`. `UInt` has no niche, so there it takes an extra byte.
+/// With `Zero` null the stored bits are exactly the address.
+pub struct NonNullPtr<T, PTR: Pointer, NULL: Null = Zero> {
+    value: PTR::NonZero,
+    phantom: PhantomData<(T, NULL)>,
+}
+
+impl<T, PTR: Pointer, NULL: Null> NonNullPtr<T, PTR, NULL> {
+    pub fn new(ptr: TypedPtr<T, PTR>) -> Option<Self> {
+        ptr.address().to_non_zero(NULL::null()).map(|value| NonNullPtr { value, phantom: PhantomData })
+    }
+
+    pub fn null_address() -> PTR { NULL::null() }
+
+    pub fn address(&self) -> PTR { PTR::from_non_zero(self.value, NULL::null()) }
+    pub fn typed(&self) -> TypedPtr<T, PTR> { unsafe { TypedPtr::new(self.address()) } }
+
+    /// # Safety
+    /// Same as `TypedPtr::read`.
+    pub unsafe fn read<MEM: Memory<PTR>>(&self, mem: &MEM) -> T { self.typed().read(mem) }
+    /// # Safety
+    /// Same as `TypedPtr::write`.
+    pub unsafe fn write<MEM: Memory<PTR>>(&self, mem: &mut MEM, value: T) { self.typed().write(mem, value) }
+}
+
+impl<T, PTR: Pointer, NULL: Null> Copy for NonNullPtr<T, PTR, NULL> {}
+
+impl<T, PTR: Pointer, NULL: Null> Clone for NonNullPtr<T, PTR, NULL> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<T, PTR: Pointer + fmt::Debug, NULL: Null> fmt::Debug for NonNullPtr<T, PTR, NULL> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("NonNullPtr").field(&self.address()).finish()
+    }
+}
+
+impl<T, PTR: Pointer, NULL: Null> PartialEq for NonNullPtr<T, PTR, NULL> {
+    fn eq(&self, other: &Self) -> bool { self.address() == other.address() }
+}
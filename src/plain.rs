@@ -0,0 +1,40 @@
+use memory::Memory;
+use region::MemoryRegion;
+use pointer::Pointer;
+use storage::Storage;
+use uint::{BitWidth, Bits, UInt};
+
+/// Type, for which every bit pattern of `size_of::<Self>()` bytes is a valid value,
+/// so it can be read from and written to arbitrary memory without `unsafe`.
+///
+/// Implement it with `#[derive(Plain)]`, that only accepts `#[repr(C)]` structs
+/// of `Plain` fields without padding.
+///
+/// # Safety
+/// The type has no padding, no invalid bit patterns and no pointers or references.
+pub unsafe trait Plain: Copy + 'static {}
+
+macro_rules! impl_plain {
+    ($($t:ty),*) => {
+        $( unsafe impl Plain for $t {} )*
+    };
+}
+
+impl_plain!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: Plain, const N: usize> Plain for [T; N] {}
+
+/// Extra bits in the last byte are ignored by `UInt::get`.
+unsafe impl<const BITS: u32> Plain for UInt<BITS> where Bits<BITS>: BitWidth {}
+
+/// `Memory`, that stays sound for accesses at any address: out of range accesses
+/// are reported or panic instead of touching host memory outside of it.
+///
+/// # Safety
+/// `read`, `write`, `try_read` and `try_write` have to be sound for any `ptr` and any `Plain` `T`,
+/// including misaligned ones.
+pub unsafe trait CheckedMemory<PTR: Copy>: Memory<PTR> {}
+
+unsafe impl<PTR: Pointer, S: Storage> CheckedMemory<PTR> for MemoryRegion<PTR, S> {}
+
+unsafe impl<PTR: Copy, M: CheckedMemory<PTR> + ?Sized> CheckedMemory<PTR> for &mut M {}
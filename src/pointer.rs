@@ -0,0 +1,51 @@
+use std::cmp::PartialOrd;
+use std::num::{NonZeroU8, NonZeroU16, NonZeroU32, NonZeroU64, NonZeroUsize};
+use std::ops::{Add, Sub, BitAnd, Not};
+
+/// Pointer arithmetic went past the range of the pointer type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OverflowErr;
+
+/// Everything `MemoryRegion`, `Layout` and the allocators need from an address type.
+pub trait Pointer: Copy where
+    Self: PartialOrd + PartialEq,
+    Self: Add<Self, Output=Self>,
+    Self: Sub<Self, Output=Self>,
+    Self: BitAnd<Self, Output=Self>,
+    Self: Not<Output=Self>,
+{
+    fn from_usize(value: usize) -> Self;
+    fn to_usize(self) -> usize;
+
+    fn max_value() -> Self;
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_sub(self, other: Self) -> Option<Self>;
+
+    /// Storage with a zero niche, when the type has one, so `Option<NonNullPtr<..>>`
+    /// takes no more space than the pointer itself.
+    type NonZero: Copy;
+    /// `None` for `null`. Values are stored XOR-ed with `null`, so that it is the one mapped to zero.
+    fn to_non_zero(self, null: Self) -> Option<Self::NonZero>;
+    fn from_non_zero(value: Self::NonZero, null: Self) -> Self;
+}
+
+macro_rules! impl_pointer {
+    ($($t:ty => $non_zero:ty),*) => {
+        $(
+            impl Pointer for $t {
+                fn from_usize(value: usize) -> Self { value as $t }
+                fn to_usize(self) -> usize { self as usize }
+
+                fn max_value() -> Self { <$t>::MAX }
+                fn checked_add(self, other: Self) -> Option<Self> { <$t>::checked_add(self, other) }
+                fn checked_sub(self, other: Self) -> Option<Self> { <$t>::checked_sub(self, other) }
+
+                type NonZero = $non_zero;
+                fn to_non_zero(self, null: Self) -> Option<$non_zero> { <$non_zero>::new(self ^ null) }
+                fn from_non_zero(value: $non_zero, null: Self) -> Self { value.get() ^ null }
+            }
+        )*
+    };
+}
+
+impl_pointer!(u8 => NonZeroU8, u16 => NonZeroU16, u32 => NonZeroU32, u64 => NonZeroU64, usize => NonZeroUsize);
@@ -0,0 +1,204 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{BitOr, RangeInclusive};
+
+use memory::{fault, Access, Memory, MemoryError, MemoryErrorKind};
+use plain::CheckedMemory;
+use pointer::Pointer;
+
+/// Set of accesses allowed to a page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Permissions {
+    bits: u8,
+}
+
+impl Permissions {
+    pub const NONE: Permissions = Permissions { bits: 0 };
+    pub const READ: Permissions = Permissions { bits: 1 };
+    pub const WRITE: Permissions = Permissions { bits: 2 };
+    pub const EXECUTE: Permissions = Permissions { bits: 4 };
+    pub const READ_WRITE: Permissions = Permissions { bits: 3 };
+    pub const READ_EXECUTE: Permissions = Permissions { bits: 5 };
+    pub const ALL: Permissions = Permissions { bits: 7 };
+
+    pub fn contains(self, other: Permissions) -> bool { self.bits & other.bits == other.bits }
+
+    pub fn allows(self, access: Access) -> bool {
+        self.contains(match access {
+            Access::Read => Permissions::READ,
+            Access::Write => Permissions::WRITE,
+            Access::Execute => Permissions::EXECUTE,
+        })
+    }
+}
+
+impl BitOr for Permissions {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self { Permissions { bits: self.bits | other.bits } }
+}
+
+const DEFAULT_PAGE_SIZE: usize = 4096;
+
+/// Checks every access to the underlying memory against per-page permissions, like an MMU.
+///
+/// All pages start with `Permissions::ALL`. Denied accesses fail with `PermissionDenied`
+/// at the address and size of the whole access, before anything is read or written,
+/// and `read` and `write` panic on them. Instruction fetches go through `try_fetch`.
+pub struct Protected<PTR: Pointer, MEM: Memory<PTR>> {
+    memory: MEM,
+    page_size: usize,
+    // permissions of all pages from each key up to the next one
+    runs: BTreeMap<usize, Permissions>,
+    phantom: PhantomData<PTR>,
+}
+
+impl<PTR: Pointer, MEM: Memory<PTR>> Protected<PTR, MEM> {
+    pub fn new(memory: MEM) -> Self { Protected::with_page_size(memory, DEFAULT_PAGE_SIZE) }
+
+    pub fn with_page_size(memory: MEM, page_size: usize) -> Self {
+        if !page_size.is_power_of_two() {
+            panic!("page size must be a power of two")
+        }
+        let mut runs = BTreeMap::new();
+        runs.insert(0, Permissions::ALL);
+        Protected { memory, page_size, runs, phantom: PhantomData }
+    }
+
+    pub fn page_size(&self) -> usize { self.page_size }
+
+    pub fn inner(&self) -> &MEM { &self.memory }
+    pub fn inner_mut(&mut self) -> &mut MEM { &mut self.memory }
+    pub fn into_inner(self) -> MEM { self.memory }
+
+    /// Permissions of the page containing `ptr`.
+    pub fn permissions(&self, ptr: PTR) -> Permissions { self.page_permissions(ptr.to_usize() / self.page_size) }
+
+    /// Sets permissions of every page, that `range` touches.
+    pub fn protect(&mut self, range: RangeInclusive<PTR>, permissions: Permissions) {
+        let (start, last) = range.into_inner();
+        if start > last {
+            panic!("range ends before it starts")
+        }
+        let first = start.to_usize() / self.page_size;
+        let last = last.to_usize() / self.page_size;
+        let after = last.checked_add(1).map(|next| (next, self.page_permissions(next)));
+        let before = first.checked_sub(1).map(|previous| self.page_permissions(previous));
+
+        let covered: Vec<usize> = self.runs.range(first..).take_while(|&(&page, _)| page <= last).map(|(&page, _)| page).collect();
+        for page in covered {
+            self.runs.remove(&page);
+        }
+        if before != Some(permissions) {
+            self.runs.insert(first, permissions);
+        }
+        if let Some((next, next_permissions)) = after {
+            if next_permissions == permissions {
+                self.runs.remove(&next);
+            } else {
+                self.runs.insert(next, next_permissions);
+            }
+        }
+    }
+
+    fn page_permissions(&self, page: usize) -> Permissions {
+        *self.runs.range(..=page).next_back().expect("page 0 always has permissions").1
+    }
+
+    fn check(&self, ptr: PTR, size: usize, access: Access) -> Result<(), MemoryError<PTR>> {
+        if size == 0 {
+            return Ok(());
+        }
+        let start = ptr.to_usize();
+        let first = start / self.page_size;
+        let last = start.saturating_add(size - 1) / self.page_size;
+        let allowed = self.page_permissions(first).allows(access)
+            && self.runs.range(first + 1..).take_while(|&(&page, _)| page <= last).all(|(_, permissions)| permissions.allows(access));
+        if allowed {
+            Ok(())
+        } else {
+            Err(MemoryError::new(MemoryErrorKind::PermissionDenied, access, ptr, size))
+        }
+    }
+}
+
+impl<PTR: Pointer, MEM: Memory<PTR>> Memory<PTR> for Protected<PTR, MEM> {
+    /// Panics on denied accesses.
+    unsafe fn read<T>(&self, ptr: PTR) -> T {
+        if let Err(error) = self.check(ptr, mem::size_of::<T>(), Access::Read) {
+            fault(error)
+        }
+        self.memory.read(ptr)
+    }
+
+    /// Panics on denied accesses.
+    unsafe fn write<T>(&mut self, ptr: PTR, value: T) {
+        if let Err(error) = self.check(ptr, mem::size_of::<T>(), Access::Write) {
+            fault(error)
+        }
+        self.memory.write(ptr, value)
+    }
+
+    unsafe fn try_read<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> {
+        self.check(ptr, mem::size_of::<T>(), Access::Read)?;
+        self.memory.try_read(ptr)
+    }
+
+    unsafe fn try_write<T>(&mut self, ptr: PTR, value: T) -> Result<(), MemoryError<PTR>> {
+        self.check(ptr, mem::size_of::<T>(), Access::Write)?;
+        self.memory.try_write(ptr, value)
+    }
+
+    unsafe fn try_fetch<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> {
+        self.check(ptr, mem::size_of::<T>(), Access::Execute)?;
+        self.memory.try_fetch(ptr)
+    }
+
+    unsafe fn read_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.check(ptr, buffer.len(), Access::Read)?;
+        self.memory.read_bytes(ptr, buffer)
+    }
+
+    unsafe fn fetch_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.check(ptr, buffer.len(), Access::Execute)?;
+        self.memory.fetch_bytes(ptr, buffer)
+    }
+
+    unsafe fn write_bytes(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> {
+        self.check(ptr, bytes.len(), Access::Write)?;
+        self.memory.write_bytes(ptr, bytes)
+    }
+
+    unsafe fn fill(&mut self, ptr: PTR, len: usize, value: u8) -> Result<(), MemoryError<PTR>> {
+        self.check(ptr, len, Access::Write)?;
+        self.memory.fill(ptr, len, value)
+    }
+
+    unsafe fn copy_within(&mut self, from: PTR, to: PTR, len: usize) -> Result<(), MemoryError<PTR>> {
+        self.check(from, len, Access::Read)?;
+        self.check(to, len, Access::Write)?;
+        self.memory.copy_within(from, to, len)
+    }
+
+    unsafe fn compare(&self, a: PTR, b: PTR, len: usize) -> Result<Ordering, MemoryError<PTR>> {
+        self.check(a, len, Access::Read)?;
+        self.check(b, len, Access::Read)?;
+        self.memory.compare(a, b, len)
+    }
+
+    fn allocated(&mut self, ptr: PTR, size: usize) { self.memory.allocated(ptr, size) }
+    fn deallocated(&mut self, ptr: PTR, size: usize) { self.memory.deallocated(ptr, size) }
+
+    unsafe fn read_metadata(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.check(ptr, buffer.len(), Access::Read)?;
+        self.memory.read_metadata(ptr, buffer)
+    }
+
+    unsafe fn write_metadata(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> {
+        self.check(ptr, bytes.len(), Access::Write)?;
+        self.memory.write_metadata(ptr, bytes)
+    }
+}
+
+unsafe impl<PTR: Pointer, MEM: CheckedMemory<PTR>> CheckedMemory<PTR> for Protected<PTR, MEM> {}
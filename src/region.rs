@@ -1,31 +1,183 @@
-use std::convert::Into;
+use std::cmp::Ordering;
 use std::marker::PhantomData;
-use std::ptr;
+use std::mem;
+use std::slice;
 
-use memory::Memory;
+use memory::{fault, Access, Memory, MemoryError, MemoryErrorKind};
+use pointer::Pointer;
+use storage::Storage;
 
-pub struct MemoryRegion<PTR: Into<usize> + Copy> {
-    data: Vec<u8>,
+/// What a region does with accesses to addresses, that are not a multiple of the alignment of `T`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlignmentPolicy {
+    /// Access as if the address was aligned, like x86.
+    Allow,
+    /// Fail with `MemoryErrorKind::Misaligned`, like 68000 address errors.
+    Trap,
+    /// Access one byte at a time, like an OS fix-up handler would.
+    Split,
+}
+
+pub struct MemoryRegion<PTR: Pointer, S: Storage = Vec<u8>> {
+    data: S,
+    alignment: AlignmentPolicy,
     phantom: PhantomData<PTR>,
 }
 
-impl<PTR: Into<usize> + Copy> MemoryRegion<PTR> {
+impl<PTR: Pointer> MemoryRegion<PTR> {
+    /// Region backed by a plain `Vec`. It has no snapshots; use `SparseRegion` for those.
     pub fn new(max: PTR) -> MemoryRegion<PTR> {
+        MemoryRegion::from_storage(vec![0; max.to_usize()])
+    }
+
+    /// Region, that covers every address representable by `PTR`.
+    pub fn full() -> MemoryRegion<PTR> {
+        MemoryRegion::from_storage(vec![0; PTR::max_value().to_usize() + 1])
+    }
+
+    pub fn with_alignment(max: PTR, alignment: AlignmentPolicy) -> MemoryRegion<PTR> {
+        let mut region = MemoryRegion::new(max);
+        region.alignment = alignment;
+        region
+    }
+}
+
+impl<PTR: Pointer, S: Storage> MemoryRegion<PTR, S> {
+    /// Region over existing bytes, that start at address 0.
+    pub fn from_storage(data: S) -> MemoryRegion<PTR, S> {
         MemoryRegion {
-            data: vec![0; max.into()],
+            data,
+            alignment: AlignmentPolicy::Allow,
             phantom: PhantomData,
         }
     }
+
+    pub fn storage(&self) -> &S { &self.data }
+    pub fn storage_mut(&mut self) -> &mut S { &mut self.data }
+    pub fn into_storage(self) -> S { self.data }
+
+    pub fn alignment(&self) -> AlignmentPolicy { self.alignment }
+    pub fn set_alignment(&mut self, alignment: AlignmentPolicy) { self.alignment = alignment }
+
+    /// Byte offset of an access to a `T` at `ptr`, if it is allowed.
+    fn check<T>(&self, ptr: PTR, access: Access) -> Result<usize, MemoryError<PTR>> {
+        let size = mem::size_of::<T>();
+        if self.alignment == AlignmentPolicy::Trap && !ptr.to_usize().is_multiple_of(mem::align_of::<T>()) {
+            return Err(MemoryError::new(MemoryErrorKind::Misaligned, access, ptr, size));
+        }
+        self.start(ptr, size, access)
+    }
+
+    /// Byte offset of an access of `size` bytes at `ptr`, if all of it is within the region.
+    fn start(&self, ptr: PTR, size: usize, access: Access) -> Result<usize, MemoryError<PTR>> {
+        let offset = ptr.to_usize();
+        if self.data.contains(offset, size) {
+            Ok(offset)
+        } else {
+            Err(MemoryError::new(MemoryErrorKind::OutOfBounds, access, ptr, size))
+        }
+    }
+
+    fn splits<T>(&self, offset: usize) -> bool {
+        self.alignment == AlignmentPolicy::Split && !offset.is_multiple_of(mem::align_of::<T>())
+    }
 }
 
-impl<PTR: Into<usize> + Copy> Memory<PTR> for MemoryRegion<PTR> {
+const COMPARE_CHUNK: usize = 256;
+
+impl<PTR: Pointer, S: Storage> Memory<PTR> for MemoryRegion<PTR, S> {
+    /// Panics if the access is out of bounds, or misaligned under `AlignmentPolicy::Trap`.
     unsafe fn read<T>(&self, ptr: PTR) -> T {
-        let read_at = self.data.as_ptr().offset(ptr.into() as isize);
-        ptr::read(read_at as *const T)
+        match self.try_read(ptr) {
+            Ok(value) => value,
+            Err(error) => fault(error),
+        }
     }
 
+    /// Panics if the access is out of bounds, or misaligned under `AlignmentPolicy::Trap`.
+    /// The bytes being overwritten are never dropped.
     unsafe fn write<T>(&mut self, ptr: PTR, value: T) {
-        let write_to = self.data.as_mut_ptr().offset(ptr.into() as isize);
-        *(write_to as *mut T) = value
+        if let Err(error) = self.try_write(ptr, value) {
+            fault(error)
+        }
+    }
+
+    unsafe fn try_read<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> {
+        let offset = self.check::<T>(ptr, Access::Read)?;
+        let mut value = mem::MaybeUninit::<T>::zeroed();
+        let bytes = slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, mem::size_of::<T>());
+        if self.splits::<T>(offset) {
+            for (i, byte) in bytes.chunks_mut(1).enumerate() {
+                self.data.read(offset + i, byte);
+            }
+        } else {
+            self.data.read(offset, bytes);
+        }
+        Ok(value.assume_init())
+    }
+
+    unsafe fn try_write<T>(&mut self, ptr: PTR, value: T) -> Result<(), MemoryError<PTR>> {
+        let offset = self.check::<T>(ptr, Access::Write)?;
+        let value = mem::ManuallyDrop::new(value);
+        let bytes = slice::from_raw_parts(&*value as *const T as *const u8, mem::size_of::<T>());
+        if self.splits::<T>(offset) {
+            for (i, byte) in bytes.chunks(1).enumerate() {
+                self.data.write(offset + i, byte);
+            }
+        } else {
+            self.data.write(offset, bytes);
+        }
+        Ok(())
+    }
+
+    unsafe fn read_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        let start = self.start(ptr, buffer.len(), Access::Read)?;
+        self.data.read(start, buffer);
+        Ok(())
+    }
+
+    unsafe fn write_bytes(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> {
+        let start = self.start(ptr, bytes.len(), Access::Write)?;
+        self.data.write(start, bytes);
+        Ok(())
     }
-}
\ No newline at end of file
+
+    unsafe fn fill(&mut self, ptr: PTR, len: usize, value: u8) -> Result<(), MemoryError<PTR>> {
+        let start = self.start(ptr, len, Access::Write)?;
+        self.data.fill(start, len, value);
+        Ok(())
+    }
+
+    unsafe fn copy_within(&mut self, from: PTR, to: PTR, len: usize) -> Result<(), MemoryError<PTR>> {
+        let source = self.start(from, len, Access::Read)?;
+        let destination = self.start(to, len, Access::Write)?;
+        self.data.copy_within(source, destination, len);
+        Ok(())
+    }
+
+    unsafe fn compare(&self, a: PTR, b: PTR, len: usize) -> Result<Ordering, MemoryError<PTR>> {
+        let a = self.start(a, len, Access::Read)?;
+        let b = self.start(b, len, Access::Read)?;
+        let (mut left, mut right) = ([0; COMPARE_CHUNK], [0; COMPARE_CHUNK]);
+        let mut done = 0;
+        while done < len {
+            let chunk = COMPARE_CHUNK.min(len - done);
+            self.data.read(a + done, &mut left[..chunk]);
+            self.data.read(b + done, &mut right[..chunk]);
+            match left[..chunk].cmp(&right[..chunk]) {
+                Ordering::Equal => done += chunk,
+                unequal => return Ok(unequal),
+            }
+        }
+        Ok(Ordering::Equal)
+    }
+
+    unsafe fn copy_to<OTHER>(&self, from: PTR, other: &mut OTHER, to: PTR, len: usize) -> Result<(), MemoryError<PTR>>
+        where OTHER: Memory<PTR> + ?Sized
+    {
+        let start = self.start(from, len, Access::Read)?;
+        let mut buffer = vec![0; len];
+        self.data.read(start, &mut buffer);
+        other.write_bytes(to, &buffer)
+    }
+}
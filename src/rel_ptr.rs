@@ -0,0 +1,83 @@
+use std::marker::PhantomData;
+
+use memory::Memory;
+use pointer::Pointer;
+use typed_ptr::TypedPtr;
+
+/// Signed integer, that can hold the distance between two addresses.
+pub trait Offset: Copy {
+    fn from_isize(value: isize) -> Option<Self>;
+    fn to_isize(self) -> isize;
+}
+
+macro_rules! impl_offset {
+    ($($t:ty),*) => {
+        $(
+            impl Offset for $t {
+                fn from_isize(value: isize) -> Option<Self> {
+                    if value as $t as isize == value { Some(value as $t) } else { None }
+                }
+                fn to_isize(self) -> isize { self as isize }
+            }
+        )*
+    };
+}
+
+impl_offset!(i8, i16, i32, i64, isize);
+
+/// Pointer to `T`, stored as an offset from the address of the `RelPtr` itself,
+/// so it stays valid when the memory holding both is moved as a whole.
+/// Offset 0 is null.
+#[derive(Debug)]
+pub struct RelPtr<T, OFFSET> {
+    offset: OFFSET,
+    phantom: PhantomData<T>,
+}
+
+impl<T, OFFSET: Copy> Copy for RelPtr<T, OFFSET> {}
+
+impl<T, OFFSET: Copy> Clone for RelPtr<T, OFFSET> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<T, OFFSET: Offset> RelPtr<T, OFFSET> {
+    pub fn null() -> Self { RelPtr { offset: OFFSET::from_isize(0).unwrap(), phantom: PhantomData } }
+
+    /// `None` if `target` is `own`, or is too far away from it to fit into `OFFSET`.
+    pub fn new<PTR: Pointer>(own: PTR, target: PTR) -> Option<Self> {
+        let distance = (target.to_usize() as isize).wrapping_sub(own.to_usize() as isize);
+        if distance == 0 {
+            return None;
+        }
+        OFFSET::from_isize(distance).map(|offset| RelPtr { offset, phantom: PhantomData })
+    }
+
+    /// Offset 0 makes a null pointer.
+    pub fn from_offset(offset: OFFSET) -> Self { RelPtr { offset, phantom: PhantomData } }
+
+    pub fn offset(&self) -> OFFSET { self.offset }
+    pub fn is_null(&self) -> bool { self.offset.to_isize() == 0 }
+
+    /// Target of this pointer, when it is stored at `own`.
+    pub fn resolve<PTR: Pointer>(&self, own: PTR) -> Option<TypedPtr<T, PTR>> {
+        let offset = self.offset.to_isize();
+        let target = if offset > 0 {
+            own.checked_add(PTR::from_usize(offset as usize))
+        } else if offset < 0 {
+            own.checked_sub(PTR::from_usize(offset.wrapping_neg() as usize))
+        } else {
+            None
+        };
+        target.map(|target| unsafe { TypedPtr::new(target) })
+    }
+}
+
+impl<T, OFFSET: Offset, PTR: Pointer> TypedPtr<RelPtr<T, OFFSET>, PTR> {
+    /// Reads the `RelPtr` stored here and resolves it against this address.
+    ///
+    /// # Safety
+    /// Same as `TypedPtr::read`.
+    pub unsafe fn load<MEM: Memory<PTR>>(&self, mem: &MEM) -> Option<TypedPtr<T, PTR>> {
+        self.read(mem).resolve(self.address())
+    }
+}
@@ -1,7 +1,9 @@
+use std::cmp::Ordering;
 use std::ptr;
+use std::slice;
 use std::sync::Mutex;
 
-use memory::Memory;
+use memory::{Memory, MemoryError};
 
 lazy_static! {
   pub static ref RUST_MEMORY: Mutex<RustMemory> = Mutex::new(RustMemory{});
@@ -15,6 +17,48 @@ impl Memory<*mut u8> for RustMemory {
     }
 
     unsafe fn write<T>(&mut self, ptr: *mut u8, value: T) {
-         *(ptr as *mut T) = value
+        ptr::write(ptr as *mut T, value)
     }
-}
\ No newline at end of file
+}
+
+impl Memory<usize> for RustMemory {
+    unsafe fn read<T>(&self, ptr: usize) -> T {
+        ptr::read(ptr as *const T)
+    }
+
+    unsafe fn write<T>(&mut self, ptr: usize, value: T) {
+        ptr::write(ptr as *mut T, value)
+    }
+
+    unsafe fn read_bytes(&self, ptr: usize, buffer: &mut [u8]) -> Result<(), MemoryError<usize>> {
+        ptr::copy_nonoverlapping(ptr as *const u8, buffer.as_mut_ptr(), buffer.len());
+        Ok(())
+    }
+
+    unsafe fn write_bytes(&mut self, ptr: usize, bytes: &[u8]) -> Result<(), MemoryError<usize>> {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), ptr as *mut u8, bytes.len());
+        Ok(())
+    }
+
+    unsafe fn fill(&mut self, ptr: usize, len: usize, value: u8) -> Result<(), MemoryError<usize>> {
+        ptr::write_bytes(ptr as *mut u8, value, len);
+        Ok(())
+    }
+
+    unsafe fn copy_within(&mut self, from: usize, to: usize, len: usize) -> Result<(), MemoryError<usize>> {
+        ptr::copy(from as *const u8, to as *mut u8, len);
+        Ok(())
+    }
+
+    unsafe fn compare(&self, a: usize, b: usize, len: usize) -> Result<Ordering, MemoryError<usize>> {
+        let a = slice::from_raw_parts(a as *const u8, len);
+        let b = slice::from_raw_parts(b as *const u8, len);
+        Ok(a.cmp(b))
+    }
+
+    unsafe fn copy_to<OTHER>(&self, from: usize, other: &mut OTHER, to: usize, len: usize) -> Result<(), MemoryError<usize>>
+        where OTHER: Memory<usize> + ?Sized
+    {
+        other.write_bytes(to, slice::from_raw_parts(from as *const u8, len))
+    }
+}
@@ -0,0 +1,258 @@
+use std::cmp::Ordering;
+use std::mem;
+use std::num::NonZeroU32;
+use std::ops::{Add, Sub, BitAnd, Not};
+use std::ptr;
+
+use memory::{Memory, MemoryError};
+use plain::CheckedMemory;
+use pointer::Pointer;
+
+/// Real-mode `segment:offset` far pointer.
+///
+/// Arithmetic is done per component, and, like on a real 8086, never carries
+/// from the offset into the segment, so allocators operate within one segment.
+/// Pointers are ordered by segment first, then by offset.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct SegmentedPtr {
+    segment: u16,
+    offset: u16,
+}
+
+impl SegmentedPtr {
+    pub fn new(segment: u16, offset: u16) -> Self { SegmentedPtr { segment, offset } }
+
+    pub fn segment(&self) -> u16 { self.segment }
+    pub fn offset(&self) -> u16 { self.offset }
+
+    /// Linear address before any A20 masking, up to 0x10FFEF.
+    pub fn linear(&self) -> u32 { ((self.segment as u32) << 4) + self.offset as u32 }
+
+    fn packed(&self) -> u32 { ((self.segment as u32) << 16) | self.offset as u32 }
+}
+
+impl PartialOrd for SegmentedPtr {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for SegmentedPtr {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.segment, self.offset).cmp(&(other.segment, other.offset))
+    }
+}
+
+impl Add for SegmentedPtr {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        SegmentedPtr::new(self.segment.wrapping_add(other.segment), self.offset.wrapping_add(other.offset))
+    }
+}
+
+impl Sub for SegmentedPtr {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        SegmentedPtr::new(self.segment.wrapping_sub(other.segment), self.offset.wrapping_sub(other.offset))
+    }
+}
+
+impl BitAnd for SegmentedPtr {
+    type Output = Self;
+    fn bitand(self, other: Self) -> Self {
+        SegmentedPtr::new(self.segment & other.segment, self.offset & other.offset)
+    }
+}
+
+impl Not for SegmentedPtr {
+    type Output = Self;
+    fn not(self) -> Self { SegmentedPtr::new(!self.segment, !self.offset) }
+}
+
+impl Pointer for SegmentedPtr {
+    /// Offset in segment 0.
+    fn from_usize(value: usize) -> Self { SegmentedPtr::new(0, value as u16) }
+    fn to_usize(self) -> usize { self.linear() as usize }
+
+    fn max_value() -> Self { SegmentedPtr::new(u16::MAX, u16::MAX) }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        match (self.segment.checked_add(other.segment), self.offset.checked_add(other.offset)) {
+            (Some(segment), Some(offset)) => Some(SegmentedPtr::new(segment, offset)),
+            _ => None,
+        }
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        match (self.segment.checked_sub(other.segment), self.offset.checked_sub(other.offset)) {
+            (Some(segment), Some(offset)) => Some(SegmentedPtr::new(segment, offset)),
+            _ => None,
+        }
+    }
+
+    type NonZero = NonZeroU32;
+    fn to_non_zero(self, null: Self) -> Option<NonZeroU32> { NonZeroU32::new(self.packed() ^ null.packed()) }
+    fn from_non_zero(value: NonZeroU32, null: Self) -> Self {
+        let packed = value.get() ^ null.packed();
+        SegmentedPtr::new((packed >> 16) as u16, packed as u16)
+    }
+}
+
+const ONE_MEGABYTE: u32 = 1 << 20;
+
+/// Resolves far pointers to linear addresses of the underlying memory.
+///
+/// With the A20 line disabled (the default, as on an 8086) addresses past 1 MiB wrap around to 0.
+pub struct SegmentedMemory<MEM: Memory<u32>> {
+    memory: MEM,
+    a20: bool,
+}
+
+impl<MEM: Memory<u32>> SegmentedMemory<MEM> {
+    pub fn new(memory: MEM) -> Self { SegmentedMemory { memory, a20: false } }
+
+    pub fn a20(&self) -> bool { self.a20 }
+    pub fn set_a20(&mut self, enabled: bool) { self.a20 = enabled }
+
+    pub fn inner(&self) -> &MEM { &self.memory }
+    pub fn inner_mut(&mut self) -> &mut MEM { &mut self.memory }
+    pub fn into_inner(self) -> MEM { self.memory }
+
+    pub fn linear(&self, ptr: SegmentedPtr) -> u32 {
+        if self.a20 { ptr.linear() } else { ptr.linear() % ONE_MEGABYTE }
+    }
+
+    fn wraps<T>(&self, linear: u32) -> bool { self.wraps_bytes(linear, mem::size_of::<T>()) }
+
+    fn wraps_bytes(&self, linear: u32, size: usize) -> bool {
+        !self.a20 && linear as usize + size > ONE_MEGABYTE as usize
+    }
+
+    /// Linear ranges of `size` bytes at `ptr`: one, or two if they wrap around at 1 MiB.
+    fn ranges(&self, ptr: SegmentedPtr, size: usize) -> Vec<(u32, usize)> {
+        let linear = self.linear(ptr);
+        if !self.wraps_bytes(linear, size) {
+            return vec![(linear, size)];
+        }
+        let head = (ONE_MEGABYTE - linear) as usize;
+        vec![(linear, head), (0, size - head)]
+    }
+}
+
+/// Fault of a single byte of a wrapped access, reported for the whole access.
+fn wrapped<T>(error: MemoryError<u32>, ptr: SegmentedPtr) -> MemoryError<SegmentedPtr> {
+    MemoryError::new(error.kind(), error.access(), ptr, mem::size_of::<T>())
+}
+
+/// Reads a wrapped `T` byte by byte with `read_byte`.
+unsafe fn gather<T, F>(linear: u32, ptr: SegmentedPtr, read_byte: F) -> Result<T, MemoryError<SegmentedPtr>>
+    where F: Fn(u32) -> Result<u8, MemoryError<u32>>
+{
+    let mut value = mem::MaybeUninit::<T>::uninit();
+    let bytes = value.as_mut_ptr() as *mut u8;
+    for i in 0..mem::size_of::<T>() {
+        *bytes.add(i) = read_byte((linear + i as u32) % ONE_MEGABYTE).map_err(|error| wrapped::<T>(error, ptr))?;
+    }
+    Ok(value.assume_init())
+}
+
+impl<MEM: Memory<u32>> Memory<SegmentedPtr> for SegmentedMemory<MEM> {
+    unsafe fn read<T>(&self, ptr: SegmentedPtr) -> T {
+        let linear = self.linear(ptr);
+        if !self.wraps::<T>(linear) {
+            return self.memory.read(linear);
+        }
+
+        let mut value = mem::MaybeUninit::<T>::uninit();
+        let bytes = value.as_mut_ptr() as *mut u8;
+        for i in 0..mem::size_of::<T>() {
+            *bytes.add(i) = self.memory.read((linear + i as u32) % ONE_MEGABYTE);
+        }
+        value.assume_init()
+    }
+
+    unsafe fn write<T>(&mut self, ptr: SegmentedPtr, value: T) {
+        let linear = self.linear(ptr);
+        if !self.wraps::<T>(linear) {
+            return self.memory.write(linear, value);
+        }
+
+        let bytes = &value as *const T as *const u8;
+        for i in 0..mem::size_of::<T>() {
+            self.memory.write((linear + i as u32) % ONE_MEGABYTE, ptr::read(bytes.add(i)));
+        }
+        mem::forget(value);
+    }
+
+    unsafe fn try_read<T>(&self, ptr: SegmentedPtr) -> Result<T, MemoryError<SegmentedPtr>> {
+        let linear = self.linear(ptr);
+        if !self.wraps::<T>(linear) {
+            return self.memory.try_read(linear).map_err(|error| error.at(ptr));
+        }
+
+        gather(linear, ptr, |address| self.memory.try_read(address))
+    }
+
+    unsafe fn try_fetch<T>(&self, ptr: SegmentedPtr) -> Result<T, MemoryError<SegmentedPtr>> {
+        let linear = self.linear(ptr);
+        if !self.wraps::<T>(linear) {
+            return self.memory.try_fetch(linear).map_err(|error| error.at(ptr));
+        }
+        gather(linear, ptr, |address| self.memory.try_fetch(address))
+    }
+
+    unsafe fn try_write<T>(&mut self, ptr: SegmentedPtr, value: T) -> Result<(), MemoryError<SegmentedPtr>> {
+        let linear = self.linear(ptr);
+        if !self.wraps::<T>(linear) {
+            return self.memory.try_write(linear, value).map_err(|error| error.at(ptr));
+        }
+
+        // the bytes are copied out, so `value` must not be dropped on any path
+        let value = mem::ManuallyDrop::new(value);
+        let bytes = &*value as *const T as *const u8;
+        let addresses: Vec<u32> = (0..mem::size_of::<T>() as u32).map(|i| (linear + i) % ONE_MEGABYTE).collect();
+        // a fault halfway through restores the bytes already written, so the write is all or nothing
+        let previous: Vec<Option<u8>> = addresses.iter().map(|&address| self.memory.try_read(address).ok()).collect();
+        for (i, &address) in addresses.iter().enumerate() {
+            if let Err(error) = self.memory.try_write(address, ptr::read(bytes.add(i))) {
+                for (&address, &byte) in addresses[..i].iter().zip(previous.iter()) {
+                    if let Some(byte) = byte {
+                        let _ = self.memory.try_write(address, byte);
+                    }
+                }
+                return Err(wrapped::<T>(error, ptr));
+            }
+        }
+        Ok(())
+    }
+
+    fn allocated(&mut self, ptr: SegmentedPtr, size: usize) {
+        for (linear, size) in self.ranges(ptr, size) {
+            self.memory.allocated(linear, size)
+        }
+    }
+
+    fn deallocated(&mut self, ptr: SegmentedPtr, size: usize) {
+        for (linear, size) in self.ranges(ptr, size) {
+            self.memory.deallocated(linear, size)
+        }
+    }
+
+    unsafe fn read_metadata(&self, ptr: SegmentedPtr, buffer: &mut [u8]) -> Result<(), MemoryError<SegmentedPtr>> {
+        let mut done = 0;
+        for (linear, size) in self.ranges(ptr, buffer.len()) {
+            self.memory.read_metadata(linear, &mut buffer[done..done + size]).map_err(|error| error.at(ptr))?;
+            done += size;
+        }
+        Ok(())
+    }
+
+    unsafe fn write_metadata(&mut self, ptr: SegmentedPtr, bytes: &[u8]) -> Result<(), MemoryError<SegmentedPtr>> {
+        let mut done = 0;
+        for (linear, size) in self.ranges(ptr, bytes.len()) {
+            self.memory.write_metadata(linear, &bytes[done..done + size]).map_err(|error| error.at(ptr))?;
+            done += size;
+        }
+        Ok(())
+    }
+}
+
+unsafe impl<MEM: CheckedMemory<u32>> CheckedMemory<SegmentedPtr> for SegmentedMemory<MEM> {}
@@ -0,0 +1,345 @@
+use std::cell::{Ref, RefCell};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::mem;
+
+use memory::{Access, Memory, MemoryError};
+use plain::CheckedMemory;
+use pointer::Pointer;
+
+/// What a byte of the checked memory holds, as far as the program is concerned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShadowState {
+    /// Not part of any allocation, either never allocated or freed.
+    Unallocated,
+    /// Allocated, but not written since.
+    Uninitialized,
+    Initialized,
+}
+
+const PAGE_SIZE: usize = 4096;
+
+/// `ShadowState` of every byte of an address space. Bytes start `Unallocated`,
+/// and pages, that are entirely unallocated, take no space.
+pub struct ShadowMap {
+    pages: BTreeMap<usize, Box<[ShadowState; PAGE_SIZE]>>,
+}
+
+impl ShadowMap {
+    pub fn new() -> Self { ShadowMap { pages: BTreeMap::new() } }
+
+    pub fn state(&self, address: usize) -> ShadowState {
+        match self.pages.get(&(address / PAGE_SIZE)) {
+            Some(page) => page[address % PAGE_SIZE],
+            None => ShadowState::Unallocated,
+        }
+    }
+
+    /// Sets the state of `len` bytes at `address`.
+    pub fn set(&mut self, address: usize, len: usize, state: ShadowState) {
+        ShadowMap::chunks(address, len, |index, within, chunk| {
+            if state == ShadowState::Unallocated {
+                if chunk == PAGE_SIZE {
+                    self.pages.remove(&index);
+                    return;
+                }
+                if !self.pages.contains_key(&index) {
+                    return;
+                }
+            }
+            self.page_mut(index)[within..within + chunk].iter_mut().for_each(|byte| *byte = state);
+        })
+    }
+
+    /// Marks the `Uninitialized` bytes among `len` bytes at `address` as `Initialized`.
+    pub fn initialize(&mut self, address: usize, len: usize) {
+        ShadowMap::chunks(address, len, |index, within, chunk| {
+            if let Some(page) = self.pages.get_mut(&index) {
+                for byte in page[within..within + chunk].iter_mut().filter(|byte| **byte == ShadowState::Uninitialized) {
+                    *byte = ShadowState::Initialized;
+                }
+            }
+        })
+    }
+
+    /// Offset and state of the first of `len` bytes at `address`, whose state doesn't satisfy `ok`.
+    fn find<F: Fn(ShadowState) -> bool>(&self, address: usize, len: usize, ok: F) -> Option<(usize, ShadowState)> {
+        let mut found = None;
+        let mut done = 0;
+        ShadowMap::chunks(address, len, |index, within, chunk| {
+            if found.is_none() {
+                found = match self.pages.get(&index) {
+                    Some(page) => page[within..within + chunk].iter().position(|&state| !ok(state))
+                        .map(|i| (done + i, page[within + i])),
+                    None if !ok(ShadowState::Unallocated) => Some((done, ShadowState::Unallocated)),
+                    None => None,
+                };
+            }
+            done += chunk;
+        });
+        found
+    }
+
+    fn page_mut(&mut self, index: usize) -> &mut [ShadowState; PAGE_SIZE] {
+        self.pages.entry(index).or_insert_with(|| Box::new([ShadowState::Unallocated; PAGE_SIZE]))
+    }
+
+    /// Calls `f` with (page index, offset within the page, length) of every page `len` bytes at `address` touch.
+    /// Stops at the end of the address space.
+    fn chunks<F: FnMut(usize, usize, usize)>(address: usize, len: usize, mut f: F) {
+        let mut done = 0;
+        while done < len {
+            let current = match address.checked_add(done) {
+                Some(current) => current,
+                None => return,
+            };
+            let within = current % PAGE_SIZE;
+            let chunk = (PAGE_SIZE - within).min(len - done);
+            f(current / PAGE_SIZE, within, chunk);
+            done += chunk;
+        }
+    }
+}
+
+impl Default for ShadowMap {
+    fn default() -> Self { ShadowMap::new() }
+}
+
+/// Block handed out by an allocator, as seen through `Memory::allocated` and `Memory::deallocated`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Allocation<PTR> {
+    start: PTR,
+    size: usize,
+    freed: bool,
+}
+
+impl<PTR: Copy> Allocation<PTR> {
+    pub fn start(&self) -> PTR { self.start }
+    pub fn size(&self) -> usize { self.size }
+    pub fn is_freed(&self) -> bool { self.freed }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// Access to bytes, that were never allocated.
+    Unallocated,
+    /// Access to bytes of an allocation, that has been freed.
+    UseAfterFree,
+    /// Read of allocated bytes, that were never written.
+    Uninitialized,
+}
+
+/// Bad access found by `Sanitizer`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Report<PTR> {
+    violation: Violation,
+    access: Access,
+    address: PTR,
+    size: usize,
+    allocation: Option<Allocation<PTR>>,
+}
+
+impl<PTR: Copy> Report<PTR> {
+    pub fn violation(&self) -> Violation { self.violation }
+    pub fn access(&self) -> Access { self.access }
+    /// Address of the first bad byte.
+    pub fn address(&self) -> PTR { self.address }
+    /// Size of the whole access.
+    pub fn size(&self) -> usize { self.size }
+    /// Allocation, that owns or owned the bad byte.
+    pub fn allocation(&self) -> Option<Allocation<PTR>> { self.allocation }
+}
+
+impl<PTR: Pointer> fmt::Display for Report<PTR> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let violation = match self.violation {
+            Violation::Unallocated => "unallocated",
+            Violation::UseAfterFree => "use after free in",
+            Violation::Uninitialized => "uninitialized",
+        };
+        let access = match self.access {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::Execute => "execute",
+        };
+        write!(f, "{} {} of {} bytes at {:#x}", violation, access, self.size, self.address.to_usize())?;
+        match self.allocation {
+            Some(allocation) => write!(f, ", {} bytes into {}allocation of {} bytes at {:#x}",
+                self.address.to_usize() - allocation.start.to_usize(),
+                if allocation.freed { "freed " } else { "" },
+                allocation.size, allocation.start.to_usize()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Checks program accesses to the underlying memory against a `ShadowMap`, like Valgrind's memcheck.
+///
+/// Allocators keep the shadow map up to date through `Memory::allocated` and `Memory::deallocated`,
+/// and their own bookkeeping goes through `read_metadata` and `write_metadata` unchecked.
+/// Reads of bytes, that aren't `Initialized`, and writes to `Unallocated` bytes are reported,
+/// but still done, so the program keeps running. Memory, that no allocator manages, such as globals,
+/// has to be `mark`ed to be accessible.
+pub struct Sanitizer<PTR: Pointer, MEM: Memory<PTR>> {
+    memory: MEM,
+    shadow: ShadowMap,
+    allocations: BTreeMap<usize, Allocation<PTR>>,
+    reports: RefCell<Vec<Report<PTR>>>,
+}
+
+impl<PTR: Pointer, MEM: Memory<PTR>> Sanitizer<PTR, MEM> {
+    pub fn new(memory: MEM) -> Self {
+        Sanitizer { memory, shadow: ShadowMap::new(), allocations: BTreeMap::new(), reports: RefCell::new(Vec::new()) }
+    }
+
+    pub fn inner(&self) -> &MEM { &self.memory }
+    pub fn inner_mut(&mut self) -> &mut MEM { &mut self.memory }
+    pub fn into_inner(self) -> MEM { self.memory }
+
+    pub fn shadow(&self) -> &ShadowMap { &self.shadow }
+
+    /// Sets the state of `len` bytes at `ptr` outside of any allocation.
+    pub fn mark(&mut self, ptr: PTR, len: usize, state: ShadowState) { self.shadow.set(ptr.to_usize(), len, state) }
+
+    /// Live or freed allocation, that contains `ptr`.
+    pub fn allocation(&self, ptr: PTR) -> Option<Allocation<PTR>> {
+        let address = ptr.to_usize();
+        self.allocations.range(..=address).next_back()
+            .map(|(_, allocation)| *allocation)
+            .filter(|allocation| address - allocation.start.to_usize() < allocation.size)
+    }
+
+    /// Bad accesses found so far, oldest first.
+    pub fn reports(&self) -> Ref<'_, [Report<PTR>]> { Ref::map(self.reports.borrow(), |reports| &reports[..]) }
+    pub fn take_reports(&mut self) -> Vec<Report<PTR>> { mem::take(self.reports.get_mut()) }
+
+    fn check(&self, ptr: PTR, len: usize, access: Access) {
+        let ok = |state| match access {
+            Access::Write => state != ShadowState::Unallocated,
+            _ => state == ShadowState::Initialized,
+        };
+        if let Some((offset, state)) = self.shadow.find(ptr.to_usize(), len, ok) {
+            let address = ptr.checked_add(PTR::from_usize(offset)).unwrap_or(ptr);
+            let allocation = self.allocation(address);
+            let violation = match (state, allocation) {
+                (ShadowState::Unallocated, Some(ref allocation)) if allocation.freed => Violation::UseAfterFree,
+                (ShadowState::Unallocated, _) => Violation::Unallocated,
+                _ => Violation::Uninitialized,
+            };
+            self.reports.borrow_mut().push(Report { violation, access, address, size: len, allocation });
+        }
+    }
+
+    fn written(&mut self, ptr: PTR, len: usize) {
+        self.check(ptr, len, Access::Write);
+        self.shadow.initialize(ptr.to_usize(), len);
+    }
+
+    /// Drops records of freed allocations, that a new one at `start` overlaps.
+    fn forget_overlapping(&mut self, start: usize, size: usize) {
+        let end = start.saturating_add(size);
+        let overlapping: Vec<usize> = self.allocations.range(..end).rev()
+            .take_while(|&(&other, allocation)| other.saturating_add(allocation.size) > start)
+            .map(|(&other, _)| other)
+            .collect();
+        for other in overlapping {
+            self.allocations.remove(&other);
+        }
+    }
+}
+
+impl<PTR: Pointer, MEM: Memory<PTR>> Memory<PTR> for Sanitizer<PTR, MEM> {
+    unsafe fn read<T>(&self, ptr: PTR) -> T {
+        let value = self.memory.read(ptr);
+        self.check(ptr, mem::size_of::<T>(), Access::Read);
+        value
+    }
+
+    unsafe fn write<T>(&mut self, ptr: PTR, value: T) {
+        self.memory.write(ptr, value);
+        self.written(ptr, mem::size_of::<T>());
+    }
+
+    unsafe fn try_read<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> {
+        let value = self.memory.try_read(ptr)?;
+        self.check(ptr, mem::size_of::<T>(), Access::Read);
+        Ok(value)
+    }
+
+    unsafe fn try_write<T>(&mut self, ptr: PTR, value: T) -> Result<(), MemoryError<PTR>> {
+        self.memory.try_write(ptr, value)?;
+        self.written(ptr, mem::size_of::<T>());
+        Ok(())
+    }
+
+    unsafe fn try_fetch<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> {
+        let value = self.memory.try_fetch(ptr)?;
+        self.check(ptr, mem::size_of::<T>(), Access::Execute);
+        Ok(value)
+    }
+
+    unsafe fn read_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.memory.read_bytes(ptr, buffer)?;
+        self.check(ptr, buffer.len(), Access::Read);
+        Ok(())
+    }
+
+    unsafe fn fetch_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.memory.fetch_bytes(ptr, buffer)?;
+        self.check(ptr, buffer.len(), Access::Execute);
+        Ok(())
+    }
+
+    unsafe fn write_bytes(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> {
+        self.memory.write_bytes(ptr, bytes)?;
+        self.written(ptr, bytes.len());
+        Ok(())
+    }
+
+    unsafe fn fill(&mut self, ptr: PTR, len: usize, value: u8) -> Result<(), MemoryError<PTR>> {
+        self.memory.fill(ptr, len, value)?;
+        self.written(ptr, len);
+        Ok(())
+    }
+
+    unsafe fn copy_within(&mut self, from: PTR, to: PTR, len: usize) -> Result<(), MemoryError<PTR>> {
+        self.memory.copy_within(from, to, len)?;
+        self.check(from, len, Access::Read);
+        self.written(to, len);
+        Ok(())
+    }
+
+    unsafe fn compare(&self, a: PTR, b: PTR, len: usize) -> Result<Ordering, MemoryError<PTR>> {
+        let ordering = self.memory.compare(a, b, len)?;
+        self.check(a, len, Access::Read);
+        self.check(b, len, Access::Read);
+        Ok(ordering)
+    }
+
+    fn allocated(&mut self, ptr: PTR, size: usize) {
+        let start = ptr.to_usize();
+        self.forget_overlapping(start, size);
+        self.allocations.insert(start, Allocation { start: ptr, size, freed: false });
+        self.shadow.set(start, size, ShadowState::Uninitialized);
+        self.memory.allocated(ptr, size)
+    }
+
+    fn deallocated(&mut self, ptr: PTR, size: usize) {
+        let start = ptr.to_usize();
+        self.forget_overlapping(start, size);
+        self.allocations.insert(start, Allocation { start: ptr, size, freed: true });
+        self.shadow.set(start, size, ShadowState::Unallocated);
+        self.memory.deallocated(ptr, size)
+    }
+
+    unsafe fn read_metadata(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.memory.read_metadata(ptr, buffer)
+    }
+
+    unsafe fn write_metadata(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> {
+        self.memory.write_metadata(ptr, bytes)
+    }
+}
+
+unsafe impl<PTR: Pointer, MEM: CheckedMemory<PTR>> CheckedMemory<PTR> for Sanitizer<PTR, MEM> {}
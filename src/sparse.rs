@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use pointer::Pointer;
+use region::MemoryRegion;
+use storage::Storage;
+
+const PAGE_SIZE: usize = 4096;
+
+type Page = [u8; PAGE_SIZE];
+
+/// Storage, that allocates fixed-size pages on first write. Untouched pages read as zeroes.
+///
+/// Pages are shared with snapshots and copied on the first write after one is taken.
+pub struct Pages {
+    /// One past the last offset; can be 2^64 on 64-bit hosts.
+    len: u128,
+    pages: BTreeMap<usize, Arc<Page>>,
+}
+
+impl Pages {
+    pub const PAGE_SIZE: usize = PAGE_SIZE;
+
+    /// Pages covering offsets below `len`.
+    pub fn new(len: u128) -> Pages { Pages { len, pages: BTreeMap::new() } }
+
+    /// Number of pages, that have been allocated.
+    pub fn resident(&self) -> usize { self.pages.len() }
+
+    /// Calls `f` with every chunk of `len` bytes at `offset`, split at page boundaries:
+    /// (page index, offset within the page, offset within the chunk, chunk length).
+    fn chunks<F: FnMut(usize, usize, usize, usize)>(offset: usize, len: usize, mut f: F) {
+        let mut done = 0;
+        while done < len {
+            let address = offset + done;
+            let within = address % Pages::PAGE_SIZE;
+            let chunk = (Pages::PAGE_SIZE - within).min(len - done);
+            f(address / Pages::PAGE_SIZE, within, done, chunk);
+            done += chunk;
+        }
+    }
+
+    fn page_mut(&mut self, index: usize) -> &mut [u8] {
+        &mut Arc::make_mut(self.pages.entry(index).or_insert_with(|| Arc::new([0; PAGE_SIZE])))[..]
+    }
+
+    /// Captures the current contents, sharing pages instead of copying them.
+    pub fn snapshot(&self) -> Snapshot { Snapshot { len: self.len, pages: self.pages.clone() } }
+
+    /// Brings back the contents captured by `snapshot`. The snapshot stays valid.
+    /// Panics if it was taken of pages with another `len`.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        if snapshot.len != self.len {
+            panic!("snapshot of {} bytes can't be restored into {} bytes", snapshot.len, self.len)
+        }
+        self.pages = snapshot.pages.clone();
+    }
+}
+
+/// Contents of `Pages` at some point, see `Pages::snapshot`.
+#[derive(Clone)]
+pub struct Snapshot {
+    len: u128,
+    pages: BTreeMap<usize, Arc<Page>>,
+}
+
+impl Snapshot {
+    /// Ranges of offsets, whose bytes differ between the two snapshots, in ascending order.
+    /// Pages, that are still shared, are skipped without comparing.
+    pub fn diff(&self, other: &Snapshot) -> Vec<Range<usize>> {
+        let mut indices: Vec<usize> = self.pages.keys().chain(other.pages.keys()).cloned().collect();
+        indices.sort();
+        indices.dedup();
+
+        let zeroes = [0; PAGE_SIZE];
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for index in indices {
+            let (mine, theirs) = match (self.pages.get(&index), other.pages.get(&index)) {
+                (Some(mine), Some(theirs)) if Arc::ptr_eq(mine, theirs) => continue,
+                (mine, theirs) => (mine.map_or(&zeroes, |page| &**page), theirs.map_or(&zeroes, |page| &**page)),
+            };
+            for (i, (a, b)) in mine.iter().zip(theirs.iter()).enumerate() {
+                if a == b {
+                    continue;
+                }
+                let offset = index * PAGE_SIZE + i;
+                match ranges.last_mut() {
+                    Some(range) if range.end == offset => range.end += 1,
+                    _ => ranges.push(offset..offset + 1),
+                }
+            }
+        }
+        ranges
+    }
+}
+
+impl Storage for Pages {
+    fn contains(&self, offset: usize, size: usize) -> bool { offset as u128 + size as u128 <= self.len }
+
+    fn read(&self, offset: usize, buffer: &mut [u8]) {
+        Pages::chunks(offset, buffer.len(), |page, within, done, chunk| {
+            let target = &mut buffer[done..done + chunk];
+            match self.pages.get(&page) {
+                Some(bytes) => target.copy_from_slice(&bytes[within..within + chunk]),
+                None => target.fill(0),
+            }
+        })
+    }
+
+    fn write(&mut self, offset: usize, bytes: &[u8]) {
+        Pages::chunks(offset, bytes.len(), |page, within, done, chunk| {
+            self.page_mut(page)[within..within + chunk].copy_from_slice(&bytes[done..done + chunk])
+        })
+    }
+
+    fn fill(&mut self, offset: usize, len: usize, value: u8) {
+        Pages::chunks(offset, len, |page, within, _, chunk| {
+            if value != 0 || self.pages.contains_key(&page) {
+                self.page_mut(page)[within..within + chunk].fill(value)
+            }
+        })
+    }
+
+    fn copy_within(&mut self, from: usize, to: usize, len: usize) {
+        let mut buffer = vec![0; len];
+        self.read(from, &mut buffer);
+        self.write(to, &buffer);
+    }
+}
+
+/// `MemoryRegion`, that only allocates the pages, that have been written to,
+/// so it can span a whole 32 or 64-bit address space.
+pub type SparseRegion<PTR> = MemoryRegion<PTR, Pages>;
+
+impl<PTR: Pointer> MemoryRegion<PTR, Pages> {
+    /// Sparse region of addresses below `max`.
+    pub fn sparse(max: PTR) -> Self { MemoryRegion::from_storage(Pages::new(max.to_usize() as u128)) }
+
+    /// Sparse region, that covers every address representable by `PTR`.
+    pub fn sparse_full() -> Self { MemoryRegion::from_storage(Pages::new(PTR::max_value().to_usize() as u128 + 1)) }
+
+    pub fn resident_pages(&self) -> usize { self.storage().resident() }
+
+    /// Cheap copy-on-write capture of the region, see `Pages::snapshot`.
+    pub fn snapshot(&self) -> Snapshot { self.storage().snapshot() }
+    /// Panics if the snapshot was taken of a region of another size.
+    pub fn restore(&mut self, snapshot: &Snapshot) { self.storage_mut().restore(snapshot) }
+}
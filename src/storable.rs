@@ -0,0 +1,174 @@
+use std::array;
+
+use compressed::CompressedPtr;
+use endian::{Be, Endian, Le};
+use non_null::{NonNullPtr, Null};
+use pointer::Pointer;
+use rel_ptr::{Offset, RelPtr};
+use segmented::SegmentedPtr;
+use typed_ptr::TypedPtr;
+use uint::{BitWidth, Bits, UInt};
+
+/// Value with a fixed in-memory layout, independent of the host: primitives are stored little-endian
+/// (wrap them into `Be` for big-endian), pointers take the width of their `PTR`, and derived structs
+/// are laid out field by field like `#[repr(C)]` would, but with target sizes and alignments.
+///
+/// `#[derive(Storable)]` accepts `#[storable(packed)]`, that drops all padding,
+/// and `#[storable(pack = N)]`, that caps the alignment of fields at N bytes.
+pub trait Storable: Sized {
+    const SIZE: usize;
+    const ALIGN: usize;
+    /// Byte offsets of the fields of a derived struct.
+    const OFFSETS: &'static [usize] = &[];
+
+    /// Decodes a value from the first `SIZE` bytes.
+    fn load(bytes: &[u8]) -> Self;
+    /// Encodes the value into the first `SIZE` bytes.
+    fn store(&self, bytes: &mut [u8]);
+}
+
+macro_rules! impl_storable {
+    ($($t:ty => $size:expr),*) => {
+        $(
+            impl Storable for $t {
+                const SIZE: usize = $size;
+                const ALIGN: usize = $size;
+
+                fn load(bytes: &[u8]) -> Self {
+                    let mut raw = [0; $size];
+                    raw.copy_from_slice(&bytes[..$size]);
+                    <$t>::from_le_bytes(raw)
+                }
+                fn store(&self, bytes: &mut [u8]) { bytes[..$size].copy_from_slice(&self.to_le_bytes()) }
+            }
+        )*
+    };
+}
+
+impl_storable!(u8 => 1, u16 => 2, u32 => 4, u64 => 8, i8 => 1, i16 => 2, i32 => 4, i64 => 8);
+
+/// Stored as 64 bits on every host.
+impl Storable for usize {
+    const SIZE: usize = 8;
+    const ALIGN: usize = 8;
+
+    fn load(bytes: &[u8]) -> Self { u64::load(bytes) as usize }
+    fn store(&self, bytes: &mut [u8]) { (*self as u64).store(bytes) }
+}
+
+/// Stored as 64 bits on every host.
+impl Storable for isize {
+    const SIZE: usize = 8;
+    const ALIGN: usize = 8;
+
+    fn load(bytes: &[u8]) -> Self { i64::load(bytes) as isize }
+    fn store(&self, bytes: &mut [u8]) { (*self as i64).store(bytes) }
+}
+
+impl Storable for f32 {
+    const SIZE: usize = 4;
+    const ALIGN: usize = 4;
+
+    fn load(bytes: &[u8]) -> Self { f32::from_bits(u32::load(bytes)) }
+    fn store(&self, bytes: &mut [u8]) { self.to_bits().store(bytes) }
+}
+
+impl Storable for f64 {
+    const SIZE: usize = 8;
+    const ALIGN: usize = 8;
+
+    fn load(bytes: &[u8]) -> Self { f64::from_bits(u64::load(bytes)) }
+    fn store(&self, bytes: &mut [u8]) { self.to_bits().store(bytes) }
+}
+
+impl<T: Storable, const N: usize> Storable for [T; N] {
+    const SIZE: usize = T::SIZE * N;
+    const ALIGN: usize = T::ALIGN;
+
+    fn load(bytes: &[u8]) -> Self { array::from_fn(|i| T::load(&bytes[i * T::SIZE..])) }
+    fn store(&self, bytes: &mut [u8]) {
+        for (i, item) in self.iter().enumerate() {
+            item.store(&mut bytes[i * T::SIZE..]);
+        }
+    }
+}
+
+impl<const BITS: u32> Storable for UInt<BITS> where Bits<BITS>: BitWidth {
+    const SIZE: usize = (BITS as usize).div_ceil(8);
+    const ALIGN: usize = 1;
+
+    fn load(bytes: &[u8]) -> Self {
+        UInt::new(bytes[..Self::SIZE].iter().rev().fold(0, |value, &byte| (value << 8) | byte as u64))
+    }
+    fn store(&self, bytes: &mut [u8]) {
+        for (i, byte) in bytes[..Self::SIZE].iter_mut().enumerate() {
+            *byte = (self.get() >> (i * 8)) as u8;
+        }
+    }
+}
+
+/// Offset first, then segment, like x86 stores far pointers.
+impl Storable for SegmentedPtr {
+    const SIZE: usize = 4;
+    const ALIGN: usize = 2;
+
+    fn load(bytes: &[u8]) -> Self { SegmentedPtr::new(u16::load(&bytes[2..]), u16::load(bytes)) }
+    fn store(&self, bytes: &mut [u8]) {
+        self.offset().store(bytes);
+        self.segment().store(&mut bytes[2..]);
+    }
+}
+
+impl<const SHIFT: u32> Storable for CompressedPtr<SHIFT> {
+    const SIZE: usize = 4;
+    const ALIGN: usize = 4;
+
+    fn load(bytes: &[u8]) -> Self { CompressedPtr::new(u32::load(bytes)) }
+    fn store(&self, bytes: &mut [u8]) { self.granule().store(bytes) }
+}
+
+impl<T, PTR: Pointer + Storable> Storable for TypedPtr<T, PTR> {
+    const SIZE: usize = PTR::SIZE;
+    const ALIGN: usize = PTR::ALIGN;
+
+    fn load(bytes: &[u8]) -> Self { unsafe { TypedPtr::new(PTR::load(bytes)) } }
+    fn store(&self, bytes: &mut [u8]) { self.address().store(bytes) }
+}
+
+/// `None` is stored as the null address.
+impl<T, PTR: Pointer + Storable, NULL: Null> Storable for Option<NonNullPtr<T, PTR, NULL>> {
+    const SIZE: usize = PTR::SIZE;
+    const ALIGN: usize = PTR::ALIGN;
+
+    fn load(bytes: &[u8]) -> Self { NonNullPtr::new(<TypedPtr<T, PTR> as Storable>::load(bytes)) }
+    fn store(&self, bytes: &mut [u8]) {
+        match *self {
+            Some(ref ptr) => ptr.address().store(bytes),
+            None => NonNullPtr::<T, PTR, NULL>::null_address().store(bytes),
+        }
+    }
+}
+
+impl<T, OFFSET: Offset + Storable> Storable for RelPtr<T, OFFSET> {
+    const SIZE: usize = OFFSET::SIZE;
+    const ALIGN: usize = OFFSET::ALIGN;
+
+    fn load(bytes: &[u8]) -> Self { RelPtr::from_offset(OFFSET::load(bytes)) }
+    fn store(&self, bytes: &mut [u8]) { self.offset().store(bytes) }
+}
+
+impl<T: Endian + Storable> Storable for Le<T> {
+    const SIZE: usize = T::SIZE;
+    const ALIGN: usize = T::ALIGN;
+
+    fn load(bytes: &[u8]) -> Self { Le::new(T::load(bytes)) }
+    fn store(&self, bytes: &mut [u8]) { self.get().store(bytes) }
+}
+
+impl<T: Endian + Storable> Storable for Be<T> {
+    const SIZE: usize = T::SIZE;
+    const ALIGN: usize = T::ALIGN;
+
+    fn load(bytes: &[u8]) -> Self { Be::new(T::load(bytes).swap_bytes()) }
+    fn store(&self, bytes: &mut [u8]) { self.get().swap_bytes().store(bytes) }
+}
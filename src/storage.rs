@@ -0,0 +1,31 @@
+use std::ops::DerefMut;
+
+/// Bytes backing a `MemoryRegion`. Ranges passed to it have been checked with `contains`.
+pub trait Storage {
+    /// Whether `size` bytes starting at `offset` are all present.
+    fn contains(&self, offset: usize, size: usize) -> bool;
+    fn read(&self, offset: usize, buffer: &mut [u8]);
+    fn write(&mut self, offset: usize, bytes: &[u8]);
+    fn fill(&mut self, offset: usize, len: usize, value: u8);
+    /// Copies `len` bytes from `from` to `to`. The ranges may overlap.
+    fn copy_within(&mut self, from: usize, to: usize, len: usize);
+}
+
+/// Contiguous storages: `Vec<u8>`, `Box<[u8]>`, memory maps.
+impl<S: DerefMut<Target = [u8]>> Storage for S {
+    fn contains(&self, offset: usize, size: usize) -> bool {
+        offset.checked_add(size).is_some_and(|end| end <= (**self).len())
+    }
+    fn read(&self, offset: usize, buffer: &mut [u8]) {
+        buffer.copy_from_slice(&self[offset..offset + buffer.len()])
+    }
+    fn write(&mut self, offset: usize, bytes: &[u8]) {
+        self[offset..offset + bytes.len()].copy_from_slice(bytes)
+    }
+    fn fill(&mut self, offset: usize, len: usize, value: u8) {
+        self[offset..offset + len].fill(value)
+    }
+    fn copy_within(&mut self, from: usize, to: usize, len: usize) {
+        (**self).copy_within(from..from + len, to)
+    }
+}
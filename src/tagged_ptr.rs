@@ -0,0 +1,97 @@
+use std::marker::PhantomData;
+
+use alloc::Layout;
+use memory::Memory;
+use pointer::Pointer;
+use typed_ptr::TypedPtr;
+
+/// `TypedPtr` with a tag stored in the low `TAG_BITS` bits, that are always zero
+/// due to alignment of `T`, and in the top `HIGH_BITS` bits of `PTR`, that are always zero
+/// for pointers into a region smaller than the address space. The tag is masked off before
+/// the pointer is dereferenced.
+///
+/// `tag()` holds the low bits first: bit `TAG_BITS` of the tag is the lowest of the high bits.
+#[derive(Copy, Clone, Debug)]
+pub struct TaggedPtr<T, PTR, const TAG_BITS: u32, const HIGH_BITS: u32 = 0> {
+    raw: PTR,
+    phantom: PhantomData<T>,
+}
+
+/// `bits` ones in the low bits of an usize, if they fit.
+fn ones(bits: u32) -> Option<usize> {
+    match 1_usize.checked_shl(bits) {
+        Some(bit) => Some(bit - 1),
+        None if bits == usize::BITS => Some(usize::MAX),
+        None => None,
+    }
+}
+
+impl<T, PTR: Pointer, const TAG_BITS: u32, const HIGH_BITS: u32> TaggedPtr<T, PTR, TAG_BITS, HIGH_BITS> {
+    /// Number of bits in `PTR`.
+    fn width() -> u32 { PTR::max_value().to_usize().count_ones() }
+
+    /// Position of the lowest of the high tag bits.
+    fn high_shift() -> u32 {
+        match Self::width().checked_sub(HIGH_BITS) {
+            Some(shift) if shift >= TAG_BITS => shift,
+            _ => panic!("TAG_BITS and HIGH_BITS don't fit into the pointer"),
+        }
+    }
+
+    fn low_mask() -> usize { ones(TAG_BITS).expect("TAG_BITS don't fit into the pointer") }
+    fn high_mask() -> usize {
+        let shift = Self::high_shift();
+        ones(HIGH_BITS).and_then(|ones| ones.checked_shl(shift)).unwrap_or(0)
+    }
+
+    /// Bits of the raw pointer, that hold the tag.
+    pub fn tag_mask() -> PTR { PTR::from_usize(Self::low_mask() | Self::high_mask()) }
+
+    pub fn new(ptr: TypedPtr<T, PTR>) -> Self {
+        let align = unsafe { Layout::<PTR>::new_unchecked::<T>() }.align().to_usize();
+        if align & Self::low_mask() != 0 {
+            panic!("alignment of T leaves fewer than TAG_BITS free bits")
+        }
+        let address = ptr.address().to_usize();
+        if address & Self::low_mask() != 0 {
+            panic!("misaligned pointer")
+        }
+        if address & Self::high_mask() != 0 {
+            panic!("pointer leaves fewer than HIGH_BITS free high bits")
+        }
+        TaggedPtr { raw: ptr.address(), phantom: PhantomData }
+    }
+
+    /// # Safety
+    /// The untagged part of `raw` has to be a valid, aligned `TypedPtr` for `T`.
+    pub unsafe fn from_raw(raw: PTR) -> Self { TaggedPtr { raw, phantom: PhantomData } }
+
+    pub fn raw(&self) -> PTR { self.raw }
+    pub fn ptr(&self) -> TypedPtr<T, PTR> { unsafe { TypedPtr::new(self.raw & !Self::tag_mask()) } }
+
+    pub fn tag(&self) -> usize {
+        let raw = self.raw.to_usize();
+        let high = (raw & Self::high_mask()).checked_shr(Self::high_shift()).unwrap_or(0);
+        raw & Self::low_mask() | high.checked_shl(TAG_BITS).unwrap_or(0)
+    }
+
+    pub fn with_tag(&self, tag: usize) -> Self {
+        if tag & !ones(TAG_BITS + HIGH_BITS).expect("TAG_BITS and HIGH_BITS don't fit into an usize") != 0 {
+            panic!("tag does not fit into TAG_BITS and HIGH_BITS")
+        }
+        let high = tag.checked_shr(TAG_BITS).unwrap_or(0).checked_shl(Self::high_shift()).unwrap_or(0);
+        let raw = self.ptr().address().to_usize() | tag & Self::low_mask() | high;
+        TaggedPtr { raw: PTR::from_usize(raw), phantom: PhantomData }
+    }
+
+    /// # Safety
+    /// Same as `TypedPtr::read`: `mem` has to hold a valid `T` at the untagged address.
+    pub unsafe fn read<MEM: Memory<PTR>>(&self, mem: &MEM) -> T { self.ptr().read(mem) }
+    /// # Safety
+    /// Same as `TypedPtr::write`.
+    pub unsafe fn write<MEM: Memory<PTR>>(&self, mem: &mut MEM, value: T) { self.ptr().write(mem, value) }
+}
+
+impl<T, PTR: PartialEq, const TAG_BITS: u32, const HIGH_BITS: u32> PartialEq for TaggedPtr<T, PTR, TAG_BITS, HIGH_BITS> {
+    fn eq(&self, other: &Self) -> bool { self.raw == other.raw }
+}
@@ -1,12 +1,28 @@
-use memory::Memory;
-use region::MemoryRegion;
+use std::cmp::Ordering;
+use std::mem;
 
-#[derive(Copy, Clone)]
-struct Ref16(u16);
+use compressed::{CompressedMemory, CompressedPtr};
+use device::Device;
+use endian::{Be, ByteOrder, ByteOrderAccess, EndianMemory, Le};
+use memory::{Access, Memory, MemoryError, MemoryErrorKind};
+use memory_map::MemoryMap;
+use non_null::{AllOnes, NonNullPtr, Sentinel};
+use pointer::OverflowErr;
+use protected::{Permissions, Protected};
+use {Plain, Pointer, Storable};
+use region::{AlignmentPolicy, MemoryRegion};
+use rust_mem::RustMemory;
+use segmented::{SegmentedMemory, SegmentedPtr};
+use shadow::{Sanitizer, ShadowMap, ShadowState, Violation};
+use sparse::SparseRegion;
+use tagged_ptr::TaggedPtr;
+use transaction::Transaction;
+use tracing::{read_trace, replay, TraceEvent, TraceRing, TraceWriter, TracingMemory};
+use typed_ptr::TypedPtr;
+use uint::UInt;
 
-impl From<Ref16> for usize {
-    fn from(value: Ref16) -> usize { value.0 as usize }
-}
+#[derive(Copy, Clone, PartialEq, PartialOrd, Pointer)]
+struct Ref16(u16);
 
 #[test]
 fn it_works() {
@@ -18,3 +34,872 @@ fn it_works() {
     };
     assert_eq!(roundtrip_value, 42);
 }
+
+#[test]
+fn region_accepts_primitive_pointers() {
+    let mut region = MemoryRegion::<u64>::new(1024);
+    let roundtrip_value: u16 = unsafe {
+        region.write(1000_u64, 0xBEEF_u16);
+        region.read(1000_u64)
+    };
+    assert_eq!(roundtrip_value, 0xBEEF);
+}
+
+#[test]
+fn uint_wraps_and_packs() {
+    type U24 = UInt<24>;
+    assert_eq!(mem::size_of::<U24>(), 3);
+    assert_eq!(mem::size_of::<UInt<48>>(), 6);
+    assert_eq!(U24::new(0xFF_FFFF) + U24::new(2), U24::new(1));
+    assert_eq!(U24::new(0) - U24::new(1), U24::new(0xFF_FFFF));
+    assert_eq!(UInt::<48>::new(!0).get(), 0xFFFF_FFFF_FFFF);
+
+    let mut region = MemoryRegion::<U24>::new(U24::new(64));
+    let ptr: TypedPtr<U24, U24> = unsafe { TypedPtr::new(U24::new(7)) };
+    unsafe {
+        region.write(U24::new(10), 0xFF_u8);
+        ptr.write(&mut region, U24::new(0x12_3456));
+        assert_eq!(ptr.read(&region), U24::new(0x12_3456));
+        assert_eq!(region.read::<u8>(U24::new(10)), 0xFF);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Pointer)]
+struct GuestAddr(u16);
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Pointer)]
+struct RomAddr {
+    address: UInt<24>,
+}
+
+#[test]
+fn derived_pointers_work_with_regions() {
+    assert_eq!(GuestAddr(3) + GuestAddr(4), GuestAddr::from(7));
+    assert_eq!(u16::from(!GuestAddr(0)), 0xFFFF);
+    assert_eq!(GuestAddr(0xFFFF).checked_add(GuestAddr(1)), None);
+    assert_eq!(RomAddr::max_value().to_usize(), 0xFF_FFFF);
+
+    let mut guest = MemoryRegion::new(GuestAddr(64));
+    let mut rom = MemoryRegion::new(RomAddr::from_usize(64));
+    unsafe {
+        let ptr = TypedPtr::<RomAddr, GuestAddr>::new(GuestAddr(8));
+        ptr.write(&mut guest, RomAddr::from(UInt::new(40)));
+        rom.write(ptr.read(&guest), 0xCAFE_u16);
+        assert_eq!(rom.read::<u16>(RomAddr::from_usize(40)), 0xCAFE);
+    }
+}
+
+#[test]
+fn far_pointers_resolve_with_a20_wraparound() {
+    let mut memory = SegmentedMemory::new(MemoryRegion::<u32>::new(0x11_0000));
+    let high = SegmentedPtr::new(0xFFFF, 0x0010);
+    assert_eq!(high.linear(), 0x10_0000);
+    assert_eq!(memory.linear(high), 0);
+    unsafe {
+        let aliased = TypedPtr::<u32, SegmentedPtr>::new(SegmentedPtr::new(0x0001, 0x0000));
+        aliased.write(&mut memory, 0xDEAD_BEEF);
+        assert_eq!(memory.read::<u32>(SegmentedPtr::new(0, 0x10)), 0xDEAD_BEEF);
+
+        memory.write(SegmentedPtr::new(0xFFFF, 0x000F), 0x1234_u16);
+        assert_eq!(memory.read::<u8>(SegmentedPtr::new(0, 0)), 0x12);
+
+        memory.set_a20(true);
+        memory.write(high, 0x42_u8);
+        assert_eq!(memory.inner().read::<u8>(0x10_0000), 0x42);
+        assert_eq!(memory.read::<u8>(SegmentedPtr::new(0, 0)), 0x12);
+    }
+}
+
+#[test]
+fn tagged_pointers_mask_tags_on_access() {
+    let mut region = MemoryRegion::<u32>::new(64);
+    unsafe {
+        let untagged = TaggedPtr::<u32, u32, 2>::new(TypedPtr::new(16));
+        let tagged = untagged.with_tag(3);
+        assert_eq!(tagged.raw(), 19);
+        assert_eq!(tagged.tag(), 3);
+        assert_eq!(tagged.with_tag(1).tag(), 1);
+        assert!(tagged.ptr() == untagged.ptr());
+
+        tagged.write(&mut region, 0x0102_0304);
+        assert_eq!(untagged.read(&region), 0x0102_0304);
+        assert_eq!(region.read::<u32>(16), 0x0102_0304);
+    }
+}
+
+#[test]
+fn tagged_pointers_use_spare_high_bits() {
+    let mut region = MemoryRegion::<u32>::new(64);
+    unsafe {
+        let untagged = TaggedPtr::<u32, u32, 2, 8>::new(TypedPtr::new(16));
+        assert_eq!(TaggedPtr::<u32, u32, 2, 8>::tag_mask(), 0xFF00_0003);
+        let tagged = untagged.with_tag(0x3FE);
+        assert_eq!(tagged.raw(), 0xFF00_0012);
+        assert_eq!(tagged.tag(), 0x3FE);
+        assert!(tagged.ptr() == untagged.ptr());
+
+        tagged.write(&mut region, 7);
+        assert_eq!(region.read::<u32>(16), 7);
+    }
+    let wide = TaggedPtr::<u64, u64, 3, 61>::new(unsafe { TypedPtr::new(0) }).with_tag(usize::MAX);
+    assert_eq!((wide.raw(), wide.tag()), (u64::MAX, usize::MAX));
+}
+
+#[test]
+#[should_panic]
+fn tagged_pointers_reject_addresses_using_high_bits() {
+    TaggedPtr::<u32, u32, 2, 8>::new(unsafe { TypedPtr::new(0x0100_0000) });
+}
+
+#[test]
+#[should_panic]
+fn tagged_pointers_reject_tags_wider_than_alignment() {
+    TaggedPtr::<u16, u32, 2>::new(unsafe { TypedPtr::new(16) });
+}
+
+#[test]
+fn nullable_pointers_are_as_small_as_addresses() {
+    assert_eq!(mem::size_of::<Option<NonNullPtr<u8, u16>>>(), 2);
+    assert_eq!(mem::size_of::<Option<NonNullPtr<u8, u64, AllOnes>>>(), 8);
+    assert_eq!(mem::size_of::<Option<NonNullPtr<u8, SegmentedPtr>>>(), 4);
+    assert_eq!(mem::size_of::<Option<NonNullPtr<u8, CompressedPtr<3>>>>(), 4);
+    assert_eq!(mem::size_of::<Option<NonNullPtr<u8, GuestAddr>>>(), 2);
+    // byte arrays have no niche
+    assert_eq!(mem::size_of::<Option<NonNullPtr<u8, UInt<24>>>>(), 4);
+}
+
+#[test]
+fn nullable_pointers_use_configured_null() {
+    let zero = unsafe { TypedPtr::<u16, u16>::new(0) };
+    let ones = unsafe { TypedPtr::<u16, u16>::new(0xFFFF) };
+    let sentinel = unsafe { TypedPtr::<u16, u16>::new(0x8000) };
+
+    assert!(NonNullPtr::<u16, u16>::new(zero).is_none());
+    assert_eq!(NonNullPtr::<u16, u16>::new(ones).unwrap().address(), 0xFFFF);
+    assert!(NonNullPtr::<u16, u16, AllOnes>::new(ones).is_none());
+    assert_eq!(NonNullPtr::<u16, u16, AllOnes>::new(zero).unwrap().address(), 0);
+    assert!(NonNullPtr::<u16, u16, Sentinel<0x8000>>::new(sentinel).is_none());
+    assert_eq!(NonNullPtr::<u16, u16, Sentinel<0x8000>>::new(zero).unwrap().address(), 0);
+    assert_eq!(NonNullPtr::<u16, u16, Sentinel<0x8000>>::null_address(), 0x8000);
+
+    let mut region = MemoryRegion::<u16>::new(4);
+    let ptr = NonNullPtr::<u16, u16, AllOnes>::new(zero).unwrap();
+    unsafe {
+        ptr.write(&mut region, 0x1234);
+        assert_eq!(ptr.read(&region), 0x1234);
+    }
+}
+
+#[test]
+fn typed_pointers_walk_arrays() {
+    let mut region = MemoryRegion::<u16>::new(64);
+    let base = unsafe { TypedPtr::<u32, u16>::new(8) };
+    unsafe {
+        for i in 0..4 {
+            base.add(i).unwrap().write(&mut region, i as u32 * 10);
+        }
+        assert_eq!(region.read::<u32>(20), 30);
+        assert_eq!(base.index(&region, 2), Ok(20));
+
+        let last = base.add(3).unwrap();
+        assert_eq!(last.address(), 20);
+        assert!(last.sub(3).unwrap() == base);
+        assert_eq!(last.offset_from(&base), Ok(3));
+        assert_eq!(base.offset_from(&last), Ok(-3));
+        assert_eq!(base.cast::<u16>().add(2).unwrap().address(), 12);
+    }
+}
+
+#[test]
+fn typed_pointer_arithmetic_reports_overflow() {
+    let ptr = unsafe { TypedPtr::<u32, u8>::new(200) };
+    assert_eq!(ptr.add(13).map(|ptr| ptr.address()), Ok(252));
+    assert_eq!(ptr.add(14).map(|ptr| ptr.address()), Err(OverflowErr));
+    assert_eq!(ptr.add(usize::MAX).map(|ptr| ptr.address()), Err(OverflowErr));
+    assert_eq!(ptr.sub(51).map(|ptr| ptr.address()), Err(OverflowErr));
+
+    let wide = unsafe { TypedPtr::<[u8; 300], u8>::new(0) };
+    assert_eq!(wide.add(1).map(|ptr| ptr.address()), Err(OverflowErr));
+
+    let odd = unsafe { TypedPtr::<u16, UInt<24>>::new(UInt::new(0xFF_FFFC)) };
+    assert_eq!(odd.add(1).map(|ptr| ptr.address()), Ok(UInt::new(0xFF_FFFE)));
+    assert_eq!(odd.add(2).map(|ptr| ptr.address()), Err(OverflowErr));
+}
+
+#[test]
+fn regions_report_out_of_bounds_accesses() {
+    let mut region = MemoryRegion::<u16>::new(16);
+    unsafe {
+        assert_eq!(region.try_write(12, 0x0102_0304_u32), Ok(()));
+        assert_eq!(region.try_read::<u32>(12), Ok(0x0102_0304));
+        assert_eq!(region.try_read::<()>(16), Ok(()));
+
+        let error = region.try_read::<u32>(13).unwrap_err();
+        assert_eq!(error.kind(), MemoryErrorKind::OutOfBounds);
+        assert_eq!(error.access(), Access::Read);
+        assert_eq!(error.address(), 13);
+        assert_eq!(error.size(), 4);
+
+        let error = region.try_write(0xFFFF, 0_u8).unwrap_err();
+        assert_eq!(error, MemoryError::new(MemoryErrorKind::OutOfBounds, Access::Write, 0xFFFF, 1));
+        assert_eq!(region.read::<u32>(12), 0x0102_0304);
+    }
+}
+
+#[test]
+fn adapters_report_faults_at_their_own_addresses() {
+    let memory = SegmentedMemory::new(MemoryRegion::<u32>::new(0x1_0000));
+    let far = SegmentedPtr::new(0x1000, 0x0002);
+    let error = unsafe { memory.try_read::<u16>(far) }.unwrap_err();
+    assert_eq!(error.address(), far);
+    assert_eq!(error.size(), 2);
+    assert_eq!(error.to_string(), "out of bounds read of 2 bytes at SegmentedPtr { segment: 4096, offset: 2 }");
+}
+
+#[test]
+#[should_panic]
+fn regions_panic_on_out_of_bounds_reads() {
+    let region = MemoryRegion::<u16>::new(16);
+    unsafe { region.read::<u32>(14) };
+}
+
+#[test]
+fn wrapped_far_accesses_fault_as_a_whole() {
+    let mut memory = SegmentedMemory::new(MemoryRegion::<u32>::new(0xF_FFFF));
+    let far = SegmentedPtr::new(0xFFFF, 0x000E);
+    unsafe {
+        memory.write(SegmentedPtr::new(0xF000, 0xFFFE), 0xAA_u8);
+
+        let error = memory.try_read::<u32>(far).unwrap_err();
+        assert_eq!((error.kind(), error.access(), error.address(), error.size()), (MemoryErrorKind::OutOfBounds, Access::Read, far, 4));
+
+        let error = memory.try_write(far, 0x1234_5678_u32).unwrap_err();
+        assert_eq!((error.kind(), error.access(), error.address(), error.size()), (MemoryErrorKind::OutOfBounds, Access::Write, far, 4));
+        assert_eq!(memory.read::<u8>(SegmentedPtr::new(0xF000, 0xFFFE)), 0xAA);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Plain)]
+#[repr(C)]
+struct Sprite {
+    position: [i16; 2],
+    tile: u16,
+    palette: u8,
+    flags: u8,
+}
+
+#[test]
+fn plain_values_need_no_unsafe() {
+    let mut region = MemoryRegion::<u16>::new(64);
+    let sprite = Sprite { position: [-3, 7], tile: 0x1234, palette: 5, flags: 0x80 };
+    region.write_plain(8, sprite);
+    assert_eq!(region.read_plain::<Sprite>(8), sprite);
+    assert_eq!(region.read_plain::<u16>(12), 0x1234);
+    assert_eq!(region.try_read_plain::<Sprite>(60).unwrap_err().kind(), MemoryErrorKind::OutOfBounds);
+
+    let ptr = unsafe { TypedPtr::<f32, u16>::new(32) };
+    ptr.write_plain(&mut region, 1.5);
+    assert_eq!(ptr.read_plain(&region), 1.5);
+
+    region.write_plain(40, 0xFFFF_u16);
+    assert_eq!(region.read_plain::<UInt<12>>(40).get(), 0xFFF);
+
+    region.write_plain(51, 0x1234_5678_u32);
+    assert_eq!(region.read_plain::<u32>(51), 0x1234_5678);
+
+    let mut far = SegmentedMemory::new(MemoryRegion::<u32>::new(0x1_0000));
+    far.write_plain(SegmentedPtr::new(0x10, 4), [1_u8, 2, 3]);
+    assert_eq!(far.read_plain::<[u8; 3]>(SegmentedPtr::new(0x10, 4)), [1, 2, 3]);
+}
+
+#[test]
+fn big_endian_memory_stores_scalars_in_guest_order() {
+    let mut memory = EndianMemory::new(MemoryRegion::<u32>::new(64), ByteOrder::Big);
+    let ptr = unsafe { TypedPtr::<u32, u32>::new(8) };
+    unsafe {
+        ptr.write_endian(&mut memory, 0x0102_0304);
+        assert_eq!(ptr.read_endian(&memory), 0x0102_0304);
+    }
+    assert_eq!(memory.inner().read_plain::<[u8; 4]>(8), [1, 2, 3, 4]);
+    assert_eq!(memory.read_u32_be(8), 0x0102_0304);
+    assert_eq!(memory.read_u16_le(8), 0x0201);
+
+    memory.write_u16_le(16, 0xABCD);
+    assert_eq!(memory.inner().read_plain::<[u8; 2]>(16), [0xCD, 0xAB]);
+    assert_eq!(unsafe { memory.read_endian::<_, u16>(16) }, 0xCDAB);
+    assert_eq!(unsafe { memory.try_read_endian::<_, u16>(63) }.unwrap_err().kind(), MemoryErrorKind::OutOfBounds);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Plain)]
+#[repr(C)]
+struct Header {
+    magic: Be<u32>,
+    length: Le<u16>,
+    flags: [u8; 2],
+}
+
+#[test]
+fn endian_memory_passes_other_values_through() {
+    let mut memory = EndianMemory::new(MemoryRegion::<u32>::new(64), if cfg!(target_endian = "big") { ByteOrder::Little } else { ByteOrder::Big });
+    let header = Header { magic: Be::new(0x7F45_4C46), length: Le::new(0x0102), flags: [3, 4] };
+    memory.write_plain(0, header);
+    memory.write_plain(8, [0x0102_u16, 0x0304]);
+    memory.write_plain(12, Be::new(0x0506_u16));
+    memory.write_plain(14, Le::new(0x0708_u16));
+
+    assert_eq!(memory.read_plain::<Header>(0), header);
+    assert_eq!(memory.inner().read_plain::<Header>(0), header);
+    assert_eq!(memory.inner().read_plain::<[u8; 8]>(0), [0x7F, 0x45, 0x4C, 0x46, 0x02, 0x01, 3, 4]);
+    assert_eq!(memory.read_plain::<[u16; 2]>(8), [0x0102, 0x0304]);
+    assert_eq!(memory.inner().read_plain::<[u16; 2]>(8), [0x0102, 0x0304]);
+    assert_eq!(memory.inner().read_plain::<[u8; 4]>(12), [0x05, 0x06, 0x08, 0x07]);
+    assert_eq!(memory.read_plain::<Be<u16>>(12).get(), 0x0506);
+    assert_eq!(memory.read_plain::<Le<u16>>(14).get(), 0x0708);
+}
+
+#[test]
+fn endian_wrappers_fix_storage_order() {
+    let mut region = MemoryRegion::<u16>::new(16);
+    region.write_plain(0, Be::new(0x1122_u16));
+    region.write_plain(2, Le::new(0x3344_u16));
+    region.write_plain(4, Be::new(-2.5_f32));
+    assert_eq!(region.read_plain::<[u8; 4]>(0), [0x11, 0x22, 0x44, 0x33]);
+    assert_eq!(region.read_plain::<Be<u16>>(0).get(), 0x1122);
+    assert_eq!(region.read_u16_be(0), 0x1122);
+    assert_eq!(region.read_u16_le(2), 0x3344);
+    assert_eq!(region.read_plain::<Be<f32>>(4).get(), -2.5);
+    assert_eq!(region.read_u32_be(4), (-2.5_f32).to_bits());
+}
+
+#[test]
+fn regions_follow_their_alignment_policy() {
+    let mut allow = MemoryRegion::<u16>::new(16);
+    let mut trap = MemoryRegion::<u16>::with_alignment(16, AlignmentPolicy::Trap);
+    let mut split = MemoryRegion::<u16>::new(16);
+    split.set_alignment(AlignmentPolicy::Split);
+    assert_eq!(split.alignment(), AlignmentPolicy::Split);
+
+    unsafe {
+        assert_eq!(allow.try_write(3, 0x0102_0304_u32), Ok(()));
+        assert_eq!(allow.try_read::<u32>(3), Ok(0x0102_0304));
+        assert_eq!(split.try_write(3, 0x0102_0304_u32), Ok(()));
+        assert_eq!(split.try_read::<u32>(3), Ok(0x0102_0304));
+        assert_eq!(split.read_plain::<[u8; 4]>(3), allow.read_plain::<[u8; 4]>(3));
+
+        let error = trap.try_write(3, 0_u32).unwrap_err();
+        assert_eq!(error, MemoryError::new(MemoryErrorKind::Misaligned, Access::Write, 3, 4));
+        assert_eq!(trap.try_read::<u16>(5).unwrap_err().kind(), MemoryErrorKind::Misaligned);
+        assert_eq!(trap.try_read::<u32>(4), Ok(0));
+        assert_eq!(trap.try_read::<[u8; 3]>(5), Ok([0; 3]));
+    }
+}
+
+#[test]
+fn region_writes_do_not_drop_previous_contents() {
+    let mut region = MemoryRegion::<u16>::new(64);
+    region.write_plain(8, [0xFF_u8; 24]);
+    unsafe {
+        region.write(9, vec![1_u8, 2, 3]);
+        let stored: Vec<u8> = region.read(9);
+        assert_eq!(stored, [1, 2, 3]);
+    }
+}
+
+#[test]
+fn regions_move_bytes_in_bulk() {
+    let mut region = MemoryRegion::<u16>::new(32);
+    let mut image = MemoryRegion::<u16>::new(32);
+    unsafe {
+        region.write_bytes(4, b"hello").unwrap();
+        region.fill(9, 3, b'!').unwrap();
+        region.copy_within(4, 6, 8).unwrap();
+
+        let mut buffer = [0; 10];
+        region.read_bytes(4, &mut buffer).unwrap();
+        assert_eq!(&buffer, b"hehello!!!");
+        assert_eq!(region.compare(4, 6, 2), Ok(Ordering::Equal));
+        assert_eq!(region.compare(4, 6, 3), Ok(Ordering::Less));
+
+        region.copy_to(4, &mut image, 20, 10).unwrap();
+        assert_eq!(image.compare(20, 20, 10), Ok(Ordering::Equal));
+        assert_eq!(image.read::<[u8; 10]>(20), buffer);
+
+        let error = region.fill(30, 4, 0).unwrap_err();
+        assert_eq!(error, MemoryError::new(MemoryErrorKind::OutOfBounds, Access::Write, 30, 4));
+        assert_eq!(region.copy_to(0, &mut image, 30, 4).unwrap_err().access(), Access::Write);
+    }
+}
+
+#[test]
+fn bulk_defaults_go_byte_by_byte() {
+    let mut memory = SegmentedMemory::new(MemoryRegion::<u32>::new(0x2_0000));
+    let at = SegmentedPtr::new(0x100, 0xFFFC);
+    unsafe {
+        memory.write_bytes(at, &[1, 2, 3, 4]).unwrap();
+        memory.copy_within(at, SegmentedPtr::new(0x100, 0xFFFD), 3).unwrap();
+        let mut buffer = [0; 4];
+        memory.read_bytes(at, &mut buffer).unwrap();
+        assert_eq!(buffer, [1, 1, 2, 3]);
+        assert_eq!(memory.compare(at, SegmentedPtr::new(0, 0), 1), Ok(Ordering::Greater));
+
+        let error = memory.fill(at, 5, 0).unwrap_err();
+        assert_eq!((error.address(), error.size()), (at, 5));
+    }
+}
+
+#[test]
+fn compressed_memory_reports_bulk_faults_at_the_faulting_operand() {
+    let mut memory = CompressedMemory::<_, 3>::new(MemoryRegion::<usize>::new(64), 0);
+    let (inside, outside) = (CompressedPtr::new(1), CompressedPtr::new(7));
+    unsafe {
+        memory.write_bytes(inside, &[1, 2, 3]).unwrap();
+        memory.write_bytes(CompressedPtr::new(2), &[1, 2, 4]).unwrap();
+        assert_eq!(memory.compare(inside, CompressedPtr::new(2), 3), Ok(Ordering::Less));
+
+        assert_eq!(memory.compare(inside, outside, 16).unwrap_err().address(), outside);
+        assert_eq!(memory.compare(outside, inside, 16).unwrap_err().address(), outside);
+
+        let mut image = CompressedMemory::<_, 3>::new(MemoryRegion::<usize>::new(64), 0);
+        memory.copy_to(inside, &mut image, CompressedPtr::new(3), 3).unwrap();
+        assert_eq!(image.read::<[u8; 3]>(CompressedPtr::new(3)), [1, 2, 3]);
+        let error = memory.copy_to(outside, &mut image, inside, 16).unwrap_err();
+        assert_eq!((error.address(), error.access()), (outside, Access::Read));
+    }
+}
+
+#[test]
+fn native_memory_moves_bytes_in_bulk() {
+    let mut buffer = [0_u8; 16];
+    let base = buffer.as_mut_ptr() as usize;
+    let mut native = RustMemory();
+    unsafe {
+        native.write_bytes(base, b"abcd").unwrap();
+        native.copy_within(base, base + 2, 4).unwrap();
+        native.fill(base + 8, 2, b'z').unwrap();
+        assert_eq!(native.compare(base, base + 2, 2), Ok(Ordering::Equal));
+    }
+    assert_eq!(&buffer[..10], b"ababcd\0\0zz");
+}
+
+#[derive(Clone, Debug, PartialEq, Storable)]
+struct Record {
+    tag: u8,
+    next: TypedPtr<Record, u16>,
+    length: u32,
+    far: SegmentedPtr,
+}
+
+#[derive(Clone, Debug, PartialEq, Storable)]
+#[storable(packed)]
+struct PackedRecord {
+    tag: u8,
+    address: UInt<24>,
+    length: Be<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Storable)]
+#[storable(pack = 2)]
+struct PairOfWords(u8, u32);
+
+#[test]
+fn storable_layout_follows_target_widths() {
+    assert_eq!(Record::OFFSETS, &[0, 2, 4, 8]);
+    assert_eq!((Record::SIZE, Record::ALIGN), (12, 4));
+    assert_eq!(PackedRecord::OFFSETS, &[0, 1, 4]);
+    assert_eq!((PackedRecord::SIZE, PackedRecord::ALIGN), (8, 1));
+    assert_eq!(PairOfWords::OFFSETS, &[0, 2]);
+    assert_eq!((PairOfWords::SIZE, PairOfWords::ALIGN), (6, 2));
+    assert_eq!(<Option<NonNullPtr<Record, UInt<24>>>>::SIZE, 3);
+}
+
+#[test]
+fn storable_values_are_stored_field_by_field() {
+    let mut region = MemoryRegion::<u16>::new(64);
+    let record = Record {
+        tag: 7,
+        next: unsafe { TypedPtr::new(0x1234) },
+        length: 0x0102_0304,
+        far: SegmentedPtr::new(0xB800, 0x0010),
+    };
+    let packed = PackedRecord { tag: 1, address: UInt::new(0x0A_0B0C), length: Be::new(0x0102_0304) };
+    unsafe {
+        let at = TypedPtr::<Record, u16>::new(16);
+        at.set(&mut region, &record).unwrap();
+        assert_eq!(at.get(&region), Ok(record));
+        assert_eq!(
+            region.read_plain::<[u8; 12]>(16),
+            [7, 0, 0x34, 0x12, 4, 3, 2, 1, 0x10, 0, 0x00, 0xB8]
+        );
+
+        region.store(40, &packed).unwrap();
+        assert_eq!(region.read_plain::<[u8; 8]>(40), [1, 0x0C, 0x0B, 0x0A, 1, 2, 3, 4]);
+        assert_eq!(region.load::<PackedRecord>(40), Ok(packed));
+        assert_eq!(region.load::<Record>(56).unwrap_err().kind(), MemoryErrorKind::OutOfBounds);
+    }
+}
+
+#[test]
+fn sparse_regions_allocate_pages_on_write() {
+    let mut region = SparseRegion::<u32>::sparse_full();
+    assert_eq!(region.read_plain::<u64>(0xFFFF_FFF8), 0);
+    unsafe { region.fill(0x1000, 0x3000, 0).unwrap() };
+    assert_eq!(region.resident_pages(), 0);
+
+    region.write_plain(0xFFFF_FFFC, 0xDEAD_BEEF_u32);
+    region.write_plain(0x0000_0FFE, 0x0102_0304_u32);
+    assert_eq!(region.resident_pages(), 3);
+    assert_eq!(region.read_plain::<u32>(0xFFFF_FFFC), 0xDEAD_BEEF);
+    assert_eq!(region.read_plain::<u32>(0x0000_0FFE), 0x0102_0304);
+    assert_eq!(region.read_plain::<u16>(0x0000_1000), 0x0102);
+    assert_eq!(region.try_read_plain::<u64>(0xFFFF_FFFC).unwrap_err().kind(), MemoryErrorKind::OutOfBounds);
+
+    unsafe {
+        region.copy_within(0x0FFE, 0x2_0FFE, 4).unwrap();
+        assert_eq!(region.compare(0x0FFE, 0x2_0FFE, 4), Ok(Ordering::Equal));
+    }
+    assert_eq!(region.resident_pages(), 5);
+}
+
+#[test]
+fn sparse_regions_span_64_bit_address_spaces() {
+    let mut region = SparseRegion::<u64>::sparse_full();
+    let top = unsafe { TypedPtr::<u64, u64>::new(u64::MAX - 7) };
+    top.write_plain(&mut region, 42);
+    assert_eq!(top.read_plain(&region), 42);
+    assert_eq!(region.read_plain::<u64>(1 << 40), 0);
+    assert_eq!(region.resident_pages(), 1);
+
+    let limited = SparseRegion::<u64>::sparse(1 << 32);
+    assert!(limited.try_read_plain::<u8>(1 << 32).is_err());
+}
+
+#[test]
+fn memory_maps_route_accesses_to_backends() {
+    let mut ram = MemoryRegion::<u16>::new(0x800);
+    let mut rom = MemoryRegion::<u16>::new(0x8000);
+    rom.write_plain(0x7FFC, 0x8000_u16);
+    {
+        let mut bus = MemoryMap::new();
+        bus.map(0x0000, 0x07FF, &mut ram);
+        bus.mirror(0x0800, 0x1FFF, 0x0000, 0x800);
+        bus.map(0x8000, 0xFFFF, &mut rom);
+        bus.map(0x2000, 0x2007, MemoryRegion::<u16>::new(8));
+
+        bus.write_plain(0x0010, 0xAB_u8);
+        assert_eq!(bus.read_plain::<u8>(0x0810), 0xAB);
+        assert_eq!(bus.read_plain::<u8>(0x1810), 0xAB);
+        bus.write_plain(0x1FFF, 0x1234_u16);
+        assert_eq!(bus.read_plain::<u8>(0x07FF), 0x34);
+        assert_eq!(bus.read_plain::<u8>(0x2000), 0x12);
+        assert_eq!(bus.read_plain::<u16>(0xFFFC), 0x8000);
+
+        let error = bus.try_read_plain::<u32>(0x2006).unwrap_err();
+        assert_eq!((error.kind(), error.address(), error.size()), (MemoryErrorKind::OutOfBounds, 0x2008, 2));
+        bus.set_open_bus(Some(0xFF));
+        assert_eq!(bus.read_plain::<u32>(0x2006), 0xFFFF_0000);
+        bus.write_plain(0x4000, 0_u8);
+        assert_eq!(bus.read_plain::<u8>(0x4000), 0xFF);
+    }
+    assert_eq!(ram.read_plain::<u8>(0x0010), 0xAB);
+}
+
+#[test]
+fn later_mappings_overlay_earlier_ones() {
+    let mut bus = MemoryMap::<u32>::new();
+    bus.map(0, 0xFFFF, MemoryRegion::<u32>::new(0x1_0000));
+    bus.map(0x100, 0x1FF, MemoryRegion::<u32>::new(0x100));
+    bus.write_plain(0xFC, [1_u8; 8]);
+    bus.write_plain(0x1FE, [2_u8; 4]);
+    assert_eq!(bus.read_plain::<[u8; 8]>(0xFC), [1; 8]);
+    assert_eq!(bus.read_plain::<[u8; 6]>(0x1FC), [0, 0, 2, 2, 2, 2]);
+
+    let mut below = [0; 4];
+    unsafe { bus.read_bytes(0x100, &mut below).unwrap() };
+    assert_eq!(below, [1; 4]);
+}
+
+struct Vic {
+    raster: u8,
+    border: u8,
+    accesses: Vec<(u16, usize)>,
+}
+
+impl Device<u16> for Vic {
+    fn read(&mut self, offset: u16, size: usize) -> u64 {
+        self.accesses.push((offset, size));
+        match offset {
+            0x12 => {
+                self.raster = self.raster.wrapping_add(1);
+                self.raster as u64
+            },
+            0x20 => self.border as u64,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u16, size: usize, value: u64) {
+        self.accesses.push((offset, size));
+        if offset == 0x20 {
+            self.border = value as u8;
+        }
+    }
+}
+
+#[test]
+fn devices_mounted_over_ram_run_on_access() {
+    let mut vic = Vic { raster: 0, border: 0, accesses: Vec::new() };
+    let mut ram = MemoryRegion::<u16>::full();
+    {
+        let mut bus = MemoryMap::new();
+        bus.map(0, 0xFFFF, &mut ram);
+        bus.mount(0xD000, 0xD3FF, &mut vic);
+
+        unsafe {
+            assert_eq!(bus.read::<u8>(0xD012), 1);
+            assert_eq!(bus.read::<u8>(0xD012), 2);
+            bus.write(0xD020, 0x0E_u16);
+            bus.write(0xCFFF, 0xAA_u8);
+            assert_eq!(bus.read::<[u8; 3]>(0xD011), [0, 3, 0]);
+            assert_eq!(bus.read::<u16>(0xCFFF), 0x00AA);
+        }
+    }
+    assert_eq!(vic.border, 0x0E);
+    assert_eq!(vic.accesses, vec![(0x12, 1), (0x12, 1), (0x20, 2), (0x11, 1), (0x12, 1), (0x13, 1), (0x00, 1)]);
+    assert_eq!(ram.read_plain::<u8>(0xD020), 0);
+}
+
+#[test]
+fn protected_pages_deny_accesses_without_permission() {
+    let mut memory = Protected::new(MemoryRegion::<u32>::new(0x4000));
+    memory.write_plain(0x1000, 0xC3_u8);
+    memory.protect(0x0000..=0x0FFF, Permissions::NONE);
+    memory.protect(0x1000..=0x1FFF, Permissions::EXECUTE);
+    memory.protect(0x2000..=0x2001, Permissions::READ);
+    assert_eq!(memory.permissions(0x2FFF), Permissions::READ);
+    assert_eq!(memory.permissions(0x3000), Permissions::ALL);
+
+    let error = memory.try_read_plain::<u8>(0x0800).unwrap_err();
+    assert_eq!((error.kind(), error.access(), error.address(), error.size()), (MemoryErrorKind::PermissionDenied, Access::Read, 0x0800, 1));
+    assert_eq!(error.to_string(), "permission denied read of 1 bytes at 2048");
+    assert_eq!(unsafe { memory.try_fetch::<u8>(0x1000) }, Ok(0xC3));
+    assert!(memory.try_read_plain::<u8>(0x1000).is_err());
+
+    let error = memory.try_write_plain(0x2FFE, 0_u32).unwrap_err();
+    assert_eq!((error.access(), error.address(), error.size()), (Access::Write, 0x2FFE, 4));
+    let error = memory.try_read_plain::<u32>(0x1FFE).unwrap_err();
+    assert_eq!(error.access(), Access::Read);
+    let error = unsafe { memory.try_fetch::<u16>(0x0FFF) }.unwrap_err();
+    assert_eq!(error.access(), Access::Execute);
+    assert!(unsafe { memory.fill(0x2F00, 0x200, 0) }.is_err());
+    assert_eq!(memory.inner().read_plain::<u8>(0x3000), 0);
+
+    memory.protect(0x0000..=0x2FFF, Permissions::ALL);
+    memory.write_plain(0x0FFE, 0x1234_5678_u32);
+    assert_eq!(memory.read_plain::<u32>(0x0FFE), 0x1234_5678);
+}
+
+#[test]
+fn fetches_reach_protected_pages_through_adapters() {
+    let mut protected = Protected::new(MemoryRegion::<u32>::new(0x2000));
+    protected.write_plain(0x1000, [0x90_u8, 0xC3]);
+    protected.protect(0x0000..=0x0FFF, Permissions::READ_WRITE);
+    protected.protect(0x1000..=0x1FFF, Permissions::EXECUTE);
+
+    let endian = EndianMemory::new(&mut protected, ByteOrder::Big);
+    unsafe {
+        assert_eq!(endian.try_fetch::<[u8; 2]>(0x1000), Ok([0x90, 0xC3]));
+        assert_eq!(endian.try_read::<u8>(0x1000).unwrap_err().access(), Access::Read);
+        let mut code = [0; 2];
+        endian.fetch_bytes(0x1000, &mut code).unwrap();
+        assert_eq!(code, [0x90, 0xC3]);
+    }
+
+    let mut bus = MemoryMap::new();
+    bus.map(0x8000, 0x9FFF, &mut protected);
+    unsafe {
+        assert_eq!(bus.try_fetch::<[u8; 2]>(0x9000), Ok([0x90, 0xC3]));
+        let error = bus.try_read::<u16>(0x9000).unwrap_err();
+        assert_eq!((error.kind(), error.access(), error.address()), (MemoryErrorKind::PermissionDenied, Access::Read, 0x9000));
+        let error = bus.try_fetch::<u16>(0x8FFF).unwrap_err();
+        assert_eq!((error.kind(), error.access()), (MemoryErrorKind::PermissionDenied, Access::Execute));
+    }
+}
+
+#[test]
+#[should_panic]
+fn protected_write_panics_on_read_only_page() {
+    let mut rom = Protected::with_page_size(MemoryRegion::<u16>::new(0x100), 0x10);
+    rom.protect(0x00..=0xFF, Permissions::READ_EXECUTE);
+    rom.write_plain(0x10, 1_u8);
+}
+
+#[test]
+fn tracing_memory_records_accesses_into_a_ring() {
+    let mut memory = TracingMemory::new(MemoryRegion::<u16>::new(0x100), TraceRing::new(3));
+    memory.set_type_names(true);
+    memory.write_plain(0x10, 0x1234_u16);
+    assert_eq!(memory.read_plain::<u8>(0x11), 0x12);
+    assert!(memory.try_write_plain(0xFF, 0_u16).is_err());
+    unsafe { memory.fill(0x20, 2, 0xAA).unwrap() };
+    memory.write_plain(0x30, [1_u8, 2]);
+
+    let ring = memory.sink();
+    assert_eq!(ring.dropped(), 1);
+    let lines: Vec<String> = ring.events().map(|event| event.to_string()).collect();
+    assert_eq!(lines, vec!["R 0x11 12 u8", "W 0x20 aaaa", "W 0x30 0102 [u8;2]"]);
+    let last = ring.events().last().unwrap();
+    assert_eq!((last.access(), last.address(), last.bytes()), (Access::Write, 0x30, &[1_u8, 2][..]));
+}
+
+#[test]
+fn write_traces_replay_to_the_same_state() {
+    let mut memory = TracingMemory::new(MemoryRegion::<u32>::new(0x1000), TraceWriter::new(Vec::new()));
+    memory.write_plain(0x100, 0xDEAD_BEEF_u32);
+    memory.write_plain(0x104, 7_u8);
+    assert_eq!(memory.read_plain::<u32>(0x100), 0xDEAD_BEEF);
+    unsafe {
+        memory.copy_within(0x100, 0x200, 5).unwrap();
+        memory.write_bytes(0x300, &[]).unwrap();
+    }
+    let (original, writer) = memory.into_inner();
+    let log = writer.into_inner().unwrap();
+
+    let events: Vec<TraceEvent<u32>> = read_trace(&log[..]).unwrap();
+    assert_eq!(events.len(), 6);
+    assert_eq!(events[0].type_name(), None);
+    let mut fresh = MemoryRegion::<u32>::new(0x1000);
+    replay(&events, &mut fresh).unwrap();
+    assert_eq!(fresh.storage(), original.storage());
+
+    assert!(read_trace::<u32, _>(&b"W 0x10 123\n"[..]).is_err());
+    assert!(read_trace::<u16, _>(&b"R 0x10000 00\n"[..]).is_err());
+}
+
+#[test]
+fn sparse_snapshots_share_pages_and_diff() {
+    let mut region = SparseRegion::<u32>::sparse(16 << 20);
+    region.write_plain(0x10, 0xFFFF_FFFF_u32);
+    region.write_plain(0x80_0000, 1_u8);
+    let before = region.snapshot();
+
+    region.write_plain(0x12, 0_u8);
+    region.write_plain(0x0FFF, 0x0101_u16);
+    region.write_plain(0xF0_0000, 7_u8);
+    assert_eq!(region.resident_pages(), 4);
+    let after = region.snapshot();
+    assert_eq!(before.diff(&after), vec![0x12..0x13, 0x0FFF..0x1001, 0xF0_0000..0xF0_0001]);
+    assert_eq!(after.diff(&after), vec![]);
+
+    region.restore(&before);
+    assert_eq!(region.read_plain::<u32>(0x10), 0xFFFF_FFFF);
+    assert_eq!(region.read_plain::<u16>(0x0FFF), 0);
+    assert_eq!(region.read_plain::<u8>(0xF0_0000), 0);
+    assert_eq!(region.snapshot().diff(&before), vec![]);
+    region.restore(&after);
+    assert_eq!(region.read_plain::<u8>(0xF0_0000), 7);
+}
+
+#[test]
+#[should_panic]
+fn snapshots_only_restore_into_regions_of_the_same_size() {
+    let snapshot = SparseRegion::<u32>::sparse(1 << 20).snapshot();
+    SparseRegion::<u32>::sparse(2 << 20).restore(&snapshot);
+}
+
+#[test]
+fn transactions_undo_writes_to_savepoints() {
+    let mut region = MemoryRegion::<u16>::new(0x100);
+    region.write_plain(0x10, 0x1111_u16);
+    {
+        let mut transaction = Transaction::new(&mut region);
+        transaction.write_plain(0x10, 0x2222_u16);
+        let outer = transaction.savepoint();
+        unsafe { transaction.fill(0x20, 4, 0xEE).unwrap() };
+        let inner = transaction.savepoint();
+        transaction.write_plain(0x11, 0x33_u8);
+        assert!(transaction.try_write_plain(0xFF, 0_u16).is_err());
+        assert_eq!(transaction.read_plain::<u16>(0x10), 0x3322);
+
+        transaction.rollback_to(inner);
+        assert_eq!(transaction.read_plain::<u16>(0x10), 0x2222);
+        transaction.rollback_to(outer);
+        assert_eq!(transaction.read_plain::<u32>(0x20), 0);
+        assert_eq!(transaction.writes(), 1);
+        unsafe { transaction.copy_within(0x10, 0x30, 2).unwrap() };
+        transaction.commit();
+    }
+    assert_eq!(region.read_plain::<[u16; 2]>(0x10), [0x2222, 0]);
+    assert_eq!(region.read_plain::<u16>(0x30), 0x2222);
+
+    let mut transaction = Transaction::new(region);
+    transaction.write_plain(0x10, [0xFF_u8; 0x40]);
+    let region = transaction.abort();
+    assert_eq!(region.read_plain::<u16>(0x10), 0x2222);
+    assert_eq!(region.read_plain::<u16>(0x30), 0x2222);
+    assert_eq!(region.read_plain::<u8>(0x4F), 0);
+}
+
+#[test]
+#[should_panic(expected = "savepoint was already rolled back")]
+fn transactions_reject_savepoints_discarded_by_a_rollback() {
+    let mut transaction = Transaction::new(MemoryRegion::<u16>::new(0x100));
+    let first = transaction.savepoint();
+    transaction.write_plain(0x10, 1_u8);
+    transaction.write_plain(0x11, 2_u8);
+    let discarded = transaction.savepoint();
+    transaction.rollback_to(first);
+    transaction.rollback_to(first);
+    for i in 0..3 {
+        transaction.write_plain(0x20 + i, 3_u8);
+    }
+    let last = transaction.savepoint();
+    transaction.rollback_to(last);
+    transaction.rollback_to(discarded);
+}
+
+#[test]
+#[should_panic(expected = "belongs to another transaction")]
+fn transactions_reject_savepoints_of_other_transactions() {
+    let mut other = Transaction::new(MemoryRegion::<u16>::new(0x100));
+    other.write_plain(0x10, 1_u8);
+    let foreign = other.savepoint();
+    let mut transaction = Transaction::new(MemoryRegion::<u16>::new(0x100));
+    transaction.write_plain(0x10, 1_u8);
+    transaction.write_plain(0x11, 1_u8);
+    transaction.rollback_to(foreign);
+}
+
+#[test]
+fn shadow_maps_track_byte_states() {
+    let mut shadow = ShadowMap::new();
+    shadow.set(0x0FFE, 4, ShadowState::Uninitialized);
+    shadow.initialize(0x0FFD, 2);
+    assert_eq!(shadow.state(0x0FFD), ShadowState::Unallocated);
+    assert_eq!(shadow.state(0x0FFE), ShadowState::Initialized);
+    assert_eq!(shadow.state(0x0FFF), ShadowState::Uninitialized);
+    assert_eq!(shadow.state(0x1001), ShadowState::Uninitialized);
+    shadow.set(0x1000, 0x1000, ShadowState::Unallocated);
+    assert_eq!(shadow.state(0x1001), ShadowState::Unallocated);
+
+    let mut memory = Sanitizer::new(MemoryRegion::<u32>::new(0x100));
+    memory.mark(0x80, 0x10, ShadowState::Initialized);
+    memory.write_plain(0x80, 1_u64);
+    assert_eq!(memory.read_plain::<u64>(0x88), 0);
+    memory.write_plain(0x8E, 1_u32);
+    let reports = memory.reports();
+    assert_eq!(reports.len(), 1);
+    assert_eq!((reports[0].violation(), reports[0].access(), reports[0].address()), (Violation::Unallocated, Access::Write, 0x90));
+}
+
+#[test]
+fn transactions_journal_below_the_sanitizer() {
+    let mut sanitizer = Sanitizer::new(MemoryRegion::<u32>::new(0x100));
+    sanitizer.mark(0x40, 0x10, ShadowState::Uninitialized);
+    let mut transaction = Transaction::new(&mut sanitizer);
+    transaction.write_plain(0x40, 0x1234_u32);
+    unsafe { transaction.fill(0x44, 4, 0xEE).unwrap() };
+    assert_eq!(transaction.read_plain::<u64>(0x40), 0xEEEE_EEEE_0000_1234);
+    transaction.abort();
+    assert_eq!(sanitizer.reports().len(), 0);
+    assert_eq!(unsafe { sanitizer.inner().read::<u64>(0x40) }, 0);
+}
@@ -0,0 +1,311 @@
+use std::any;
+use std::borrow::Cow;
+use std::cell::{Ref, RefCell};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::marker::PhantomData;
+use std::mem;
+use std::slice;
+
+use memory::{Access, Memory, MemoryError};
+use plain::CheckedMemory;
+use pointer::Pointer;
+
+/// Access recorded by `TracingMemory`.
+///
+/// Formats as one line of a text log: access (`R`, `W` or `X`), hex address, value bytes in hex,
+/// and the type name, if any, e.g. `W 0x1000 78563412 u32`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEvent<PTR> {
+    access: Access,
+    address: PTR,
+    bytes: Vec<u8>,
+    type_name: Option<Cow<'static, str>>,
+}
+
+impl<PTR: Pointer> TraceEvent<PTR> {
+    pub fn new(access: Access, address: PTR, bytes: Vec<u8>, type_name: Option<Cow<'static, str>>) -> Self {
+        TraceEvent { access, address, bytes, type_name }
+    }
+
+    pub fn access(&self) -> Access { self.access }
+    pub fn address(&self) -> PTR { self.address }
+    /// Value read or written, as it is laid out in memory.
+    pub fn bytes(&self) -> &[u8] { &self.bytes }
+    pub fn size(&self) -> usize { self.bytes.len() }
+    pub fn type_name(&self) -> Option<&str> { self.type_name.as_deref() }
+
+    /// Parses a line formatted by `Display`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let access = match parts.next()? {
+            "R" => Access::Read,
+            "W" => Access::Write,
+            "X" => Access::Execute,
+            _ => return None,
+        };
+        let address = parts.next()?;
+        if !address.starts_with("0x") {
+            return None;
+        }
+        let address = usize::from_str_radix(&address[2..], 16).ok()?;
+        let hex = match parts.next()? {
+            "-" => "",
+            hex => hex,
+        };
+        if hex.len() % 2 != 0 || !hex.is_ascii() {
+            return None;
+        }
+        let bytes = (0..hex.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let type_name = parts.next().map(|name| Cow::Owned(name.to_string()));
+        if parts.next().is_some() {
+            return None;
+        }
+        let ptr = PTR::from_usize(address);
+        if ptr.to_usize() != address {
+            return None;
+        }
+        Some(TraceEvent::new(access, ptr, bytes, type_name))
+    }
+}
+
+impl<PTR: Pointer> fmt::Display for TraceEvent<PTR> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = match self.access {
+            Access::Read => "R",
+            Access::Write => "W",
+            Access::Execute => "X",
+        };
+        write!(f, "{} {:#x} ", access, self.address.to_usize())?;
+        if self.bytes.is_empty() {
+            // keeps the line parseable
+            write!(f, "-")?;
+        }
+        for byte in self.bytes.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        match self.type_name {
+            // type names, like `[u8; 4]`, may contain spaces
+            Some(ref name) => write!(f, " {}", name.replace(' ', "")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Receives events recorded by `TracingMemory`.
+pub trait TraceSink<PTR> {
+    fn record(&mut self, event: TraceEvent<PTR>);
+}
+
+/// Keeps the last `capacity` events.
+pub struct TraceRing<PTR> {
+    events: VecDeque<TraceEvent<PTR>>,
+    capacity: usize,
+    dropped: u64,
+}
+
+impl<PTR> TraceRing<PTR> {
+    pub fn new(capacity: usize) -> Self {
+        TraceRing { events: VecDeque::with_capacity(capacity), capacity, dropped: 0 }
+    }
+
+    /// Oldest first.
+    pub fn events(&self) -> impl Iterator<Item = &TraceEvent<PTR>> { self.events.iter() }
+    /// Number of events pushed out of the ring.
+    pub fn dropped(&self) -> u64 { self.dropped }
+    pub fn clear(&mut self) { self.events.clear() }
+}
+
+impl<PTR> TraceSink<PTR> for TraceRing<PTR> {
+    fn record(&mut self, event: TraceEvent<PTR>) {
+        if self.capacity == 0 {
+            self.dropped += 1;
+            return;
+        }
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+            self.dropped += 1;
+        }
+        self.events.push_back(event);
+    }
+}
+
+/// Writes events as lines of text, that `read_trace` can parse back.
+/// Recording stops at the first I/O error, which `into_inner` returns.
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(writer: W) -> Self { TraceWriter { writer, error: None } }
+
+    pub fn into_inner(mut self) -> io::Result<W> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.writer.flush().map(|_| self.writer),
+        }
+    }
+}
+
+impl<PTR: Pointer, W: Write> TraceSink<PTR> for TraceWriter<W> {
+    fn record(&mut self, event: TraceEvent<PTR>) {
+        if self.error.is_none() {
+            if let Err(error) = writeln!(self.writer, "{}", event) {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+impl<PTR, S: TraceSink<PTR> + ?Sized> TraceSink<PTR> for &mut S {
+    fn record(&mut self, event: TraceEvent<PTR>) { (**self).record(event) }
+}
+
+/// Parses a log written by `TraceWriter`, skipping empty lines.
+pub fn read_trace<PTR: Pointer, R: BufRead>(reader: R) -> io::Result<Vec<TraceEvent<PTR>>> {
+    let mut events = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match TraceEvent::parse(&line) {
+            Some(event) => events.push(event),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("bad trace event on line {}", number + 1))),
+        }
+    }
+    Ok(events)
+}
+
+/// Re-applies the writes of a trace in order, ignoring other accesses.
+pub fn replay<'e, PTR, MEM, I>(events: I, memory: &mut MEM) -> Result<(), MemoryError<PTR>>
+    where PTR: Pointer + 'e, MEM: Memory<PTR> + ?Sized, I: IntoIterator<Item = &'e TraceEvent<PTR>>
+{
+    for event in events.into_iter().filter(|event| event.access == Access::Write) {
+        unsafe { memory.write_bytes(event.address, &event.bytes)? };
+    }
+    Ok(())
+}
+
+/// Records every successful access to the underlying memory into a `TraceSink`.
+///
+/// Failed accesses are not recorded. Bulk operations are recorded as single events without a type name;
+/// `copy_within` and `fill` as writes of the resulting bytes.
+pub struct TracingMemory<PTR: Pointer, MEM: Memory<PTR>, SINK: TraceSink<PTR>> {
+    memory: MEM,
+    sink: RefCell<SINK>,
+    type_names: bool,
+    phantom: PhantomData<PTR>,
+}
+
+unsafe fn bytes_of<T>(value: &T) -> Vec<u8> {
+    slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()).to_vec()
+}
+
+impl<PTR: Pointer, MEM: Memory<PTR>, SINK: TraceSink<PTR>> TracingMemory<PTR, MEM, SINK> {
+    pub fn new(memory: MEM, sink: SINK) -> Self {
+        TracingMemory { memory, sink: RefCell::new(sink), type_names: false, phantom: PhantomData }
+    }
+
+    /// Whether to record Rust type names of typed accesses. Off by default.
+    pub fn type_names(&self) -> bool { self.type_names }
+    pub fn set_type_names(&mut self, enabled: bool) { self.type_names = enabled }
+
+    pub fn inner(&self) -> &MEM { &self.memory }
+    pub fn inner_mut(&mut self) -> &mut MEM { &mut self.memory }
+    pub fn sink(&self) -> Ref<'_, SINK> { self.sink.borrow() }
+    pub fn sink_mut(&mut self) -> &mut SINK { self.sink.get_mut() }
+    pub fn into_inner(self) -> (MEM, SINK) { (self.memory, self.sink.into_inner()) }
+
+    fn record(&self, access: Access, address: PTR, bytes: Vec<u8>, type_name: Option<&'static str>) {
+        let type_name = if self.type_names { type_name.map(Cow::Borrowed) } else { None };
+        self.sink.borrow_mut().record(TraceEvent::new(access, address, bytes, type_name));
+    }
+}
+
+impl<PTR: Pointer, MEM: Memory<PTR>, SINK: TraceSink<PTR>> Memory<PTR> for TracingMemory<PTR, MEM, SINK> {
+    unsafe fn read<T>(&self, ptr: PTR) -> T {
+        let value = self.memory.read(ptr);
+        self.record(Access::Read, ptr, bytes_of(&value), Some(any::type_name::<T>()));
+        value
+    }
+
+    unsafe fn write<T>(&mut self, ptr: PTR, value: T) {
+        let bytes = bytes_of(&value);
+        self.memory.write(ptr, value);
+        self.record(Access::Write, ptr, bytes, Some(any::type_name::<T>()));
+    }
+
+    unsafe fn try_read<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> {
+        let value = self.memory.try_read(ptr)?;
+        self.record(Access::Read, ptr, bytes_of(&value), Some(any::type_name::<T>()));
+        Ok(value)
+    }
+
+    unsafe fn try_write<T>(&mut self, ptr: PTR, value: T) -> Result<(), MemoryError<PTR>> {
+        let bytes = bytes_of(&value);
+        self.memory.try_write(ptr, value)?;
+        self.record(Access::Write, ptr, bytes, Some(any::type_name::<T>()));
+        Ok(())
+    }
+
+    unsafe fn try_fetch<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> {
+        let value = self.memory.try_fetch(ptr)?;
+        self.record(Access::Execute, ptr, bytes_of(&value), Some(any::type_name::<T>()));
+        Ok(value)
+    }
+
+    unsafe fn read_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.memory.read_bytes(ptr, buffer)?;
+        self.record(Access::Read, ptr, buffer.to_vec(), None);
+        Ok(())
+    }
+
+    unsafe fn fetch_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.memory.fetch_bytes(ptr, buffer)?;
+        self.record(Access::Execute, ptr, buffer.to_vec(), None);
+        Ok(())
+    }
+
+    unsafe fn write_bytes(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> {
+        self.memory.write_bytes(ptr, bytes)?;
+        self.record(Access::Write, ptr, bytes.to_vec(), None);
+        Ok(())
+    }
+
+    unsafe fn fill(&mut self, ptr: PTR, len: usize, value: u8) -> Result<(), MemoryError<PTR>> {
+        self.memory.fill(ptr, len, value)?;
+        self.record(Access::Write, ptr, vec![value; len], None);
+        Ok(())
+    }
+
+    unsafe fn copy_within(&mut self, from: PTR, to: PTR, len: usize) -> Result<(), MemoryError<PTR>> {
+        let mut bytes = vec![0; len];
+        self.memory.read_bytes(from, &mut bytes)?;
+        self.memory.copy_within(from, to, len)?;
+        self.record(Access::Read, from, bytes.clone(), None);
+        self.record(Access::Write, to, bytes, None);
+        Ok(())
+    }
+
+    fn allocated(&mut self, ptr: PTR, size: usize) { self.memory.allocated(ptr, size) }
+    fn deallocated(&mut self, ptr: PTR, size: usize) { self.memory.deallocated(ptr, size) }
+
+    unsafe fn read_metadata(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.memory.read_metadata(ptr, buffer)?;
+        self.record(Access::Read, ptr, buffer.to_vec(), None);
+        Ok(())
+    }
+
+    unsafe fn write_metadata(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> {
+        self.memory.write_metadata(ptr, bytes)?;
+        self.record(Access::Write, ptr, bytes.to_vec(), None);
+        Ok(())
+    }
+}
+
+unsafe impl<PTR: Pointer, MEM: CheckedMemory<PTR>, SINK: TraceSink<PTR>> CheckedMemory<PTR> for TracingMemory<PTR, MEM, SINK> {}
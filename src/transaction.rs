@@ -0,0 +1,180 @@
+use std::cmp::Ordering;
+use std::mem;
+use std::slice;
+use std::sync::atomic::{self, AtomicUsize};
+
+use memory::{fault, Access, Memory, MemoryError};
+use plain::CheckedMemory;
+use pointer::Pointer;
+
+/// Position in the journal of a `Transaction`, that it can be rolled back to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Savepoint {
+    position: usize,
+    /// Sequence number of the journal entry before `position`, or of the transaction at position 0.
+    seq: usize,
+}
+
+/// Source of sequence numbers, unique across all transactions.
+static NEXT_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+fn next_seq() -> usize { NEXT_SEQ.fetch_add(1, atomic::Ordering::Relaxed) }
+
+/// Journaled change, that rolling back undoes.
+enum Change<PTR> {
+    Write { ptr: PTR, original: Vec<u8> },
+    Allocated { ptr: PTR, size: usize },
+    Deallocated { ptr: PTR, size: usize },
+}
+
+struct Entry<PTR> {
+    seq: usize,
+    change: Change<PTR>,
+}
+
+/// Journals the original bytes of every write to the underlying memory, so they can be undone.
+///
+/// Writes go to the memory right away; `abort` and `rollback_to` write the old bytes back, newest first.
+/// Dropping a transaction keeps its writes, like `commit`. To undo `FreeList` allocations too,
+/// create it `with_anchor`, so its whole state is in the journaled memory, and `attach` again afterwards.
+///
+/// Journaling and undoing go through `read_metadata` and `write_metadata`, so a `Sanitizer` underneath
+/// does not report them. `allocated` and `deallocated` are journaled too, and undone by calling the other one,
+/// so the bytes of a deallocation, that was undone, count as uninitialized again.
+pub struct Transaction<PTR: Pointer, MEM: Memory<PTR>> {
+    memory: MEM,
+    seq: usize,
+    journal: Vec<Entry<PTR>>,
+}
+
+impl<PTR: Pointer, MEM: Memory<PTR>> Transaction<PTR, MEM> {
+    pub fn new(memory: MEM) -> Self { Transaction { memory, seq: next_seq(), journal: Vec::new() } }
+
+    pub fn inner(&self) -> &MEM { &self.memory }
+
+    /// Keeps all writes and returns the memory.
+    pub fn commit(self) -> MEM { self.memory }
+
+    /// Undoes all writes and returns the memory.
+    pub fn abort(mut self) -> MEM {
+        let start = Savepoint { position: 0, seq: self.seq };
+        self.rollback_to(start);
+        self.memory
+    }
+
+    /// Marks the current state. Savepoints nest: rolling back to one discards those taken after it.
+    pub fn savepoint(&self) -> Savepoint {
+        let position = self.journal.len();
+        Savepoint { position, seq: self.seq_before(position) }
+    }
+
+    fn seq_before(&self, position: usize) -> usize {
+        match position {
+            0 => self.seq,
+            _ => self.journal[position - 1].seq,
+        }
+    }
+
+    /// Undoes the writes made since `savepoint`. The savepoint stays usable.
+    ///
+    /// Panics if `savepoint` was discarded by rolling back to an earlier one, or was taken of another transaction.
+    pub fn rollback_to(&mut self, savepoint: Savepoint) {
+        if savepoint.position > self.journal.len() || self.seq_before(savepoint.position) != savepoint.seq {
+            panic!("savepoint was already rolled back or belongs to another transaction")
+        }
+        while self.journal.len() > savepoint.position {
+            let entry = self.journal.pop().expect("journal is longer than the savepoint");
+            match entry.change {
+                Change::Write { ptr, original } => if let Err(error) = unsafe { self.memory.write_metadata(ptr, &original) } {
+                    fault(error)
+                },
+                Change::Allocated { ptr, size } => self.memory.deallocated(ptr, size),
+                Change::Deallocated { ptr, size } => self.memory.allocated(ptr, size),
+            }
+        }
+    }
+
+    /// Number of journaled writes and allocator hook calls.
+    pub fn writes(&self) -> usize { self.journal.len() }
+
+    /// Saves the `len` bytes at `ptr` before they are overwritten.
+    /// The entry stays, even if the write fails, because it might have been partially done.
+    fn journal(&mut self, ptr: PTR, len: usize) -> Result<(), MemoryError<PTR>> {
+        let mut original = vec![0; len];
+        unsafe { self.memory.read_metadata(ptr, &mut original) }
+            .map_err(|error| MemoryError::new(error.kind(), Access::Write, ptr, len))?;
+        self.record(Change::Write { ptr, original });
+        Ok(())
+    }
+
+    fn record(&mut self, change: Change<PTR>) { self.journal.push(Entry { seq: next_seq(), change }) }
+}
+
+impl<PTR: Pointer, MEM: Memory<PTR>> Memory<PTR> for Transaction<PTR, MEM> {
+    unsafe fn read<T>(&self, ptr: PTR) -> T { self.memory.read(ptr) }
+
+    /// Panics on faults, including faults saving the original bytes.
+    unsafe fn write<T>(&mut self, ptr: PTR, value: T) {
+        if let Err(error) = self.try_write(ptr, value) {
+            fault(error)
+        }
+    }
+
+    unsafe fn try_read<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> { self.memory.try_read(ptr) }
+
+    unsafe fn try_write<T>(&mut self, ptr: PTR, value: T) -> Result<(), MemoryError<PTR>> {
+        let value = mem::ManuallyDrop::new(value);
+        let bytes = slice::from_raw_parts(&*value as *const T as *const u8, mem::size_of::<T>());
+        self.write_bytes(ptr, bytes)
+    }
+
+    unsafe fn try_fetch<T>(&self, ptr: PTR) -> Result<T, MemoryError<PTR>> { self.memory.try_fetch(ptr) }
+
+    unsafe fn fetch_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.memory.fetch_bytes(ptr, buffer)
+    }
+
+    unsafe fn read_bytes(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.memory.read_bytes(ptr, buffer)
+    }
+
+    unsafe fn write_bytes(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> {
+        self.journal(ptr, bytes.len())?;
+        self.memory.write_bytes(ptr, bytes)
+    }
+
+    unsafe fn fill(&mut self, ptr: PTR, len: usize, value: u8) -> Result<(), MemoryError<PTR>> {
+        self.journal(ptr, len)?;
+        self.memory.fill(ptr, len, value)
+    }
+
+    unsafe fn copy_within(&mut self, from: PTR, to: PTR, len: usize) -> Result<(), MemoryError<PTR>> {
+        self.journal(to, len)?;
+        self.memory.copy_within(from, to, len)
+    }
+
+    unsafe fn compare(&self, a: PTR, b: PTR, len: usize) -> Result<Ordering, MemoryError<PTR>> {
+        self.memory.compare(a, b, len)
+    }
+
+    fn allocated(&mut self, ptr: PTR, size: usize) {
+        self.record(Change::Allocated { ptr, size });
+        self.memory.allocated(ptr, size)
+    }
+
+    fn deallocated(&mut self, ptr: PTR, size: usize) {
+        self.record(Change::Deallocated { ptr, size });
+        self.memory.deallocated(ptr, size)
+    }
+
+    unsafe fn read_metadata(&self, ptr: PTR, buffer: &mut [u8]) -> Result<(), MemoryError<PTR>> {
+        self.memory.read_metadata(ptr, buffer)
+    }
+
+    unsafe fn write_metadata(&mut self, ptr: PTR, bytes: &[u8]) -> Result<(), MemoryError<PTR>> {
+        self.journal(ptr, bytes.len())?;
+        self.memory.write_metadata(ptr, bytes)
+    }
+}
+
+unsafe impl<PTR: Pointer, MEM: CheckedMemory<PTR>> CheckedMemory<PTR> for Transaction<PTR, MEM> {}
@@ -1,6 +1,11 @@
 use std::cmp::Ordering;
+use std::mem;
 use std::marker::PhantomData;
-use memory::Memory;
+use alloc::Layout;
+use memory::{Memory, MemoryError};
+use plain::{CheckedMemory, Plain};
+use pointer::{OverflowErr, Pointer};
+use storable::Storable;
 
 #[derive(Copy, Clone, Debug)]
 pub struct TypedPtr<T, PTR>{
@@ -17,6 +22,94 @@ impl<T, PTR: Copy> TypedPtr<T, PTR> {
     pub unsafe fn write<MEM: Memory<PTR>>(&self, mem: &mut MEM, value: T) { mem.write(self.ptr, value) }
 }
 
+impl<T: Plain, PTR: Copy> TypedPtr<T, PTR> {
+    pub fn read_plain<MEM: CheckedMemory<PTR>>(&self, mem: &MEM) -> T { mem.read_plain(self.ptr) }
+    pub fn write_plain<MEM: CheckedMemory<PTR>>(&self, mem: &mut MEM, value: T) { mem.write_plain(self.ptr, value) }
+}
+
+impl<T: Storable, PTR: Pointer> TypedPtr<T, PTR> {
+    /// Reads `T` in its `Storable` layout.
+    ///
+    /// # Safety
+    /// Same as `Memory::load`.
+    pub unsafe fn get<MEM: Memory<PTR>>(&self, mem: &MEM) -> Result<T, MemoryError<PTR>> { mem.load(self.ptr) }
+    /// Writes `T` in its `Storable` layout.
+    ///
+    /// # Safety
+    /// Same as `Memory::store`.
+    pub unsafe fn set<MEM: Memory<PTR>>(&self, mem: &mut MEM, value: &T) -> Result<(), MemoryError<PTR>> {
+        mem.store(self.ptr, value)
+    }
+}
+
+impl<T, PTR: Pointer> TypedPtr<T, PTR> {
+    /// Size of `T` in `PTR` units.
+    fn stride() -> Result<PTR, OverflowErr> {
+        let stride = unsafe { Layout::<PTR>::new_unchecked::<T>() }.size();
+        if stride.to_usize() < mem::size_of::<T>() { Err(OverflowErr) } else { Ok(stride) }
+    }
+
+    fn scaled(count: usize) -> Result<PTR, OverflowErr> {
+        let mut stride = Self::stride()?;
+        let mut result = PTR::from_usize(0);
+        let mut count = count;
+        while count != 0 {
+            if count & 1 != 0 {
+                result = result.checked_add(stride).ok_or(OverflowErr)?;
+            }
+            count >>= 1;
+            if count != 0 {
+                stride = stride.checked_add(stride).ok_or(OverflowErr)?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Pointer to the `count`-th `T` after this one.
+    pub fn add(&self, count: usize) -> Result<Self, OverflowErr> {
+        let offset = Self::scaled(count)?;
+        self.ptr.checked_add(offset).map(|ptr| TypedPtr { ptr, phantom: PhantomData }).ok_or(OverflowErr)
+    }
+
+    /// Pointer to the `count`-th `T` before this one.
+    pub fn sub(&self, count: usize) -> Result<Self, OverflowErr> {
+        let offset = Self::scaled(count)?;
+        self.ptr.checked_sub(offset).map(|ptr| TypedPtr { ptr, phantom: PhantomData }).ok_or(OverflowErr)
+    }
+
+    /// Distance from `origin` to this pointer in `T`s, rounded toward `origin`.
+    /// Panics if `T` is zero-sized.
+    pub fn offset_from(&self, origin: &Self) -> Result<isize, OverflowErr> {
+        let stride = Self::stride()?.to_usize();
+        if stride == 0 {
+            panic!("offset_from on a zero-sized type")
+        }
+        let (distance, negative) = match self.ptr.checked_sub(origin.ptr) {
+            Some(distance) => (distance, false),
+            None => (origin.ptr - self.ptr, true),
+        };
+        let count = distance.to_usize() / stride;
+        if count > isize::MAX as usize {
+            return Err(OverflowErr);
+        }
+        Ok(if negative { -(count as isize) } else { count as isize })
+    }
+
+    /// Reads the `count`-th `T` after this one.
+    ///
+    /// # Safety
+    /// Same as `read` at the resulting address.
+    pub unsafe fn index<MEM: Memory<PTR>>(&self, mem: &MEM, count: usize) -> Result<T, OverflowErr> {
+        self.add(count).map(|ptr| ptr.read(mem))
+    }
+
+    /// Same address, viewed as a pointer to `U`.
+    ///
+    /// # Safety
+    /// The address has to be suitably aligned for `U`, if the result is read or written.
+    pub unsafe fn cast<U>(&self) -> TypedPtr<U, PTR> { TypedPtr::new(self.ptr) }
+}
+
 impl<T, PTR: PartialEq> PartialEq for TypedPtr<T, PTR> {
     fn eq(&self, other: &Self) -> bool { self.ptr == other.ptr }
 }
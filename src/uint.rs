@@ -0,0 +1,123 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, Sub, BitAnd, Not};
+
+use pointer::Pointer;
+
+/// Selects the in-memory representation of `UInt<N>`.
+pub struct Bits<const N: u32>;
+
+pub trait BitWidth {
+    type Bytes: Copy + Default + AsRef<[u8]> + AsMut<[u8]>;
+}
+
+macro_rules! impl_bit_width {
+    ($bytes:expr; $($n:expr),*) => {
+        $( impl BitWidth for Bits<$n> { type Bytes = [u8; $bytes]; } )*
+    };
+}
+
+impl_bit_width!(1; 1, 2, 3, 4, 5, 6, 7, 8);
+impl_bit_width!(2; 9, 10, 11, 12, 13, 14, 15, 16);
+impl_bit_width!(3; 17, 18, 19, 20, 21, 22, 23, 24);
+impl_bit_width!(4; 25, 26, 27, 28, 29, 30, 31, 32);
+impl_bit_width!(5; 33, 34, 35, 36, 37, 38, 39, 40);
+impl_bit_width!(6; 41, 42, 43, 44, 45, 46, 47, 48);
+impl_bit_width!(7; 49, 50, 51, 52, 53, 54, 55, 56);
+impl_bit_width!(8; 57, 58, 59, 60, 61, 62, 63, 64);
+
+/// Unsigned integer, that wraps at 2^BITS and is stored in ceil(BITS/8) little-endian bytes.
+pub struct UInt<const BITS: u32> where Bits<BITS>: BitWidth {
+    bytes: <Bits<BITS> as BitWidth>::Bytes,
+}
+
+impl<const BITS: u32> UInt<BITS> where Bits<BITS>: BitWidth {
+    pub const MASK: u64 = if BITS == 64 { !0 } else { (1 << BITS) - 1 };
+
+    pub fn new(value: u64) -> Self {
+        let mut bytes = <Bits<BITS> as BitWidth>::Bytes::default();
+        let value = value & Self::MASK;
+        for (i, byte) in bytes.as_mut().iter_mut().enumerate() {
+            *byte = (value >> (i * 8)) as u8;
+        }
+        UInt { bytes }
+    }
+
+    pub fn get(&self) -> u64 {
+        self.bytes.as_ref().iter().rev().fold(0, |value, &byte| (value << 8) | byte as u64) & Self::MASK
+    }
+}
+
+impl<const BITS: u32> Copy for UInt<BITS> where Bits<BITS>: BitWidth {}
+
+impl<const BITS: u32> Clone for UInt<BITS> where Bits<BITS>: BitWidth {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<const BITS: u32> fmt::Debug for UInt<BITS> where Bits<BITS>: BitWidth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{:#x}", self.get()) }
+}
+
+impl<const BITS: u32> PartialEq for UInt<BITS> where Bits<BITS>: BitWidth {
+    fn eq(&self, other: &Self) -> bool { self.get() == other.get() }
+}
+
+impl<const BITS: u32> Eq for UInt<BITS> where Bits<BITS>: BitWidth {}
+
+impl<const BITS: u32> PartialOrd for UInt<BITS> where Bits<BITS>: BitWidth {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl<const BITS: u32> Ord for UInt<BITS> where Bits<BITS>: BitWidth {
+    fn cmp(&self, other: &Self) -> Ordering { self.get().cmp(&other.get()) }
+}
+
+impl<const BITS: u32> Hash for UInt<BITS> where Bits<BITS>: BitWidth {
+    fn hash<H: Hasher>(&self, state: &mut H) { self.get().hash(state) }
+}
+
+impl<const BITS: u32> Add for UInt<BITS> where Bits<BITS>: BitWidth {
+    type Output = Self;
+    fn add(self, other: Self) -> Self { UInt::new(self.get().wrapping_add(other.get())) }
+}
+
+impl<const BITS: u32> Sub for UInt<BITS> where Bits<BITS>: BitWidth {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self { UInt::new(self.get().wrapping_sub(other.get())) }
+}
+
+impl<const BITS: u32> BitAnd for UInt<BITS> where Bits<BITS>: BitWidth {
+    type Output = Self;
+    fn bitand(self, other: Self) -> Self { UInt::new(self.get() & other.get()) }
+}
+
+impl<const BITS: u32> Not for UInt<BITS> where Bits<BITS>: BitWidth {
+    type Output = Self;
+    fn not(self) -> Self { UInt::new(!self.get()) }
+}
+
+impl<const BITS: u32> Pointer for UInt<BITS> where Bits<BITS>: BitWidth {
+    fn from_usize(value: usize) -> Self { UInt::new(value as u64) }
+    fn to_usize(self) -> usize { self.get() as usize }
+
+    fn max_value() -> Self { UInt::new(Self::MASK) }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        match self.get().checked_add(other.get()) {
+            Some(sum) if sum <= Self::MASK => Some(UInt::new(sum)),
+            _ => None,
+        }
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        self.get().checked_sub(other.get()).map(UInt::new)
+    }
+
+    // byte arrays have no niche, so `Option` will need an extra byte
+    type NonZero = Self;
+    fn to_non_zero(self, null: Self) -> Option<Self> {
+        if self == null { None } else { Some(UInt::new(self.get() ^ null.get())) }
+    }
+    fn from_non_zero(value: Self, null: Self) -> Self { UInt::new(value.get() ^ null.get()) }
+}